@@ -0,0 +1,471 @@
+use std::fmt::{Display, Formatter};
+
+use xml::common::Position as _;
+use xml::reader::{ParserConfig, XmlEvent};
+
+use crate::diagnostic::Position;
+use crate::formatter::escape;
+use crate::haml_parser::{xml_error, Result};
+use crate::refactor::Edit;
+
+///A HAML file as it's written, comments, white space, quotes and the order of attributes included, so that
+///changes made to it can be written back without reformatting the rest of the file. Converting the tree back to a
+///string gives the input unchanged until something is changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxTree {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Element(Element),
+    ///Text as written, entities are left escaped
+    Text(String),
+    ///A CDATA section including its `<![CDATA[` and `]]>`
+    CData(String),
+    ///Comments, processing instructions and the XML declaration, as written
+    Other(String),
+}
+
+///An element in a [SyntaxTree]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    name: String,
+    start: Position,
+    ///The white space before the element on its line, new children are indented relative to it
+    indent: String,
+    attributes: Vec<Attribute>,
+    ///What's between the last attribute and the `>` or `/>` that ends the opening tag
+    tag_end: String,
+    self_closing: bool,
+    children: Vec<Node>,
+    ///The closing tag as written e.g. `</table >`, empty for a self-closing element
+    close: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Attribute {
+    ///The white space before the attribute
+    space: String,
+    name: String,
+    ///What's between the name and the opening quote, `=` unless there's white space around it
+    equals: String,
+    quote: char,
+    ///The value as written, entities are left escaped
+    value: String,
+}
+
+impl SyntaxTree {
+    ///Parses `input`, failing the same way the parser does if it isn't well-formed XML. `file_name` is only used in
+    ///errors.
+    pub fn parse(file_name: &str, input: &str) -> Result<SyntaxTree> {
+        let mut reader = ParserConfig::new().create_reader(input.as_bytes());
+        loop {
+            match reader.next() {
+                Ok(XmlEvent::EndDocument) => break,
+                Ok(_) => {}
+                Err(e) => return Err(xml_error(file_name, &e, reader.position())),
+            }
+        }
+        let mut cursor = Cursor {
+            input,
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
+        let mut nodes = parse_nodes(&mut cursor);
+        if !cursor.rest().is_empty() {
+            nodes.push(Node::Other(cursor.rest().to_owned()));
+        }
+        Ok(SyntaxTree { nodes })
+    }
+
+    ///The document's root element
+    pub fn root(&self) -> Option<&Element> {
+        self.nodes.iter().find_map(|v| match v {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    pub fn root_mut(&mut self) -> Option<&mut Element> {
+        self.nodes.iter_mut().find_map(|v| match v {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    ///The element whose opening tag starts at `line` and `column` when the file was parsed, the position the parser
+    ///reports for it. Positions aren't updated as the tree is changed and elements added since have none.
+    pub fn element_at(&mut self, line: u64, column: u64) -> Option<&mut Element> {
+        self.root_mut()?.find_mut(&|v| v.start == Position { line, column })
+    }
+
+    ///Makes a change from a [refactoring](crate::refactor) to this file, i.e. an edit whose file is the file this
+    ///tree was parsed from. Returns false if the element or the old value isn't there e.g. the file was changed
+    ///after the document was parsed.
+    pub fn apply(&mut self, edit: &Edit) -> bool {
+        let start = edit.range.start;
+        let Some(element) = self.element_at(start.line, start.column) else {
+            return false;
+        };
+        let matches = |v: &Element| {
+            let attribute = edit.attribute.map(|attr| v.attribute(attr));
+            v.name == edit.element && (attribute.is_none() || attribute == Some(Some(edit.old.clone())))
+        };
+        //changes to settings the document doesn't keep the position of are given the position of an ancestor
+        let Some(element) = element.find_mut(&matches) else {
+            return false;
+        };
+        match edit.attribute {
+            Some(attribute) => {
+                element.set_attribute(attribute, &edit.new);
+                true
+            }
+            None => element.replace_text(&edit.old, &edit.new),
+        }
+    }
+}
+
+impl Display for SyntaxTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.nodes.iter().try_for_each(|v| write!(f, "{}", v))
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Node::Element(element) => write!(f, "{}", element),
+            Node::Text(raw) | Node::CData(raw) | Node::Other(raw) => f.write_str(raw),
+        }
+    }
+}
+
+impl Display for Element {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{}", self.name)?;
+        for attr in &self.attributes {
+            write!(f, "{}{}{}{}{}{}", attr.space, attr.name, attr.equals, attr.quote, attr.value, attr.quote)?;
+        }
+        f.write_str(&self.tag_end)?;
+        if self.self_closing {
+            return f.write_str("/>");
+        }
+        f.write_str(">")?;
+        self.children.iter().try_for_each(|v| write!(f, "{}", v))?;
+        f.write_str(&self.close)
+    }
+}
+
+impl Element {
+    ///Parses a single element e.g. a column to [append](Element::append_child) to a table
+    pub fn parse(input: &str) -> Result<Element> {
+        let tree = SyntaxTree::parse("", input)?;
+        //well-formed XML has exactly one root element
+        Ok(tree.root().cloned().unwrap_or_else(|| Element::new("")))
+    }
+
+    fn new(name: &str) -> Element {
+        Element {
+            name: name.to_owned(),
+            start: Position::default(),
+            indent: String::new(),
+            attributes: vec![],
+            tag_end: String::new(),
+            self_closing: true,
+            children: vec![],
+            close: String::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    ///Where the opening tag started when the file was parsed, 0 for elements added since
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    ///The value of the attribute `name` with entities unescaped
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        self.attributes.iter().find(|v| v.name == name).map(|v| unescape(&v.value))
+    }
+
+    ///Changes the value of the attribute `name`, keeping its place and quotes, or adds it after the others
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        let quote = self.attributes.iter().find(|v| v.name == name).map_or('"', |v| v.quote);
+        let mut escaped = escape(value, true);
+        if quote == '\'' {
+            escaped = escaped.replace("&quot;", "\"").replace('\'', "&apos;");
+        }
+        match self.attributes.iter_mut().find(|v| v.name == name) {
+            Some(attr) => attr.value = escaped,
+            None => self.attributes.push(Attribute {
+                space: " ".to_owned(),
+                name: name.to_owned(),
+                equals: "=".to_owned(),
+                quote,
+                value: escaped,
+            }),
+        }
+    }
+
+    ///Removes the attribute `name` and the white space before it, returns false if there's no such attribute
+    pub fn remove_attribute(&mut self, name: &str) -> bool {
+        let count = self.attributes.len();
+        self.attributes.retain(|v| v.name != name);
+        count != self.attributes.len()
+    }
+
+    ///The child elements, in order
+    pub fn children(&self) -> impl Iterator<Item=&Element> {
+        self.children.iter().filter_map(|v| match v {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    pub fn children_mut(&mut self) -> impl Iterator<Item=&mut Element> {
+        self.children.iter_mut().filter_map(|v| match v {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    ///The first child element called `element` whose `name` attribute is `name`, if one is given
+    pub fn child_mut(&mut self, element: &str, name: Option<&str>) -> Option<&mut Element> {
+        self.children_mut()
+            .find(|v| v.name == element && (name.is_none() || v.attribute("name").as_deref() == name))
+    }
+
+    ///The element's text and CDATA with entities unescaped, child elements are left out
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                Node::Text(raw) => text.push_str(&unescape(raw)),
+                Node::CData(raw) => text.push_str(cdata(raw)),
+                _ => {}
+            }
+        }
+        text
+    }
+
+    ///Adds `child` after the last child, on its own line, indented like the other children or one level more
+    ///than this element if it has none. A self-closing element gets a closing tag. `child` itself is written as
+    ///it is, the lines inside it aren't indented again.
+    pub fn append_child(&mut self, mut child: Element) {
+        let indent = match self.children().last() {
+            Some(last) => last.indent.clone(),
+            None => format!("{}    ", self.indent),
+        };
+        child.indent = indent.clone();
+        let last = self.children.iter().rposition(|v| !matches!(v, Node::Text(raw) if raw.trim().is_empty()));
+        let at = last.map_or(0, |i| i + 1);
+        self.children.insert(at, Node::Text(format!("\n{}", indent)));
+        self.children.insert(at + 1, Node::Element(child));
+        if at + 2 == self.children.len() {
+            self.children.push(Node::Text(format!("\n{}", self.indent)));
+        }
+        if self.self_closing {
+            self.self_closing = false;
+            self.close = format!("</{}>", self.name);
+        }
+    }
+
+    ///Removes the first child element `f` accepts along with the white space before it, returns false if there
+    ///isn't one
+    pub fn remove_child(&mut self, f: impl Fn(&Element) -> bool) -> bool {
+        let Some(i) = self.children.iter().position(|v| matches!(v, Node::Element(element) if f(element))) else {
+            return false;
+        };
+        self.children.remove(i);
+        if i > 0 && matches!(&self.children[i - 1], Node::Text(raw) if raw.trim().is_empty()) {
+            self.children.remove(i - 1);
+        }
+        true
+    }
+
+    ///This element or the first descendant `f` accepts, depth first
+    fn find_mut(&mut self, f: &dyn Fn(&Element) -> bool) -> Option<&mut Element> {
+        if f(self) {
+            return Some(self);
+        }
+        self.children_mut().find_map(|v| v.find_mut(f))
+    }
+
+    ///Replaces `old` with `new` in the first text or CDATA section that contains it. Leading and trailing white
+    ///space is ignored, the parser trims it
+    fn replace_text(&mut self, old: &str, new: &str) -> bool {
+        let (old, new) = (old.trim(), new.trim());
+        if old.is_empty() {
+            return false;
+        }
+        for child in &mut self.children {
+            match child {
+                Node::Text(raw) if raw.contains(&escape(old, false)) => {
+                    *raw = raw.replacen(&escape(old, false), &escape(new, false), 1);
+                    return true;
+                }
+                Node::CData(raw) if cdata(raw).contains(old) => {
+                    *raw = format!("<![CDATA[{}]]>", cdata(raw).replacen(old, new, 1));
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+}
+
+fn cdata(raw: &str) -> &str {
+    raw.trim_start_matches("<![CDATA[").trim_end_matches("]]>")
+}
+
+fn unescape(raw: &str) -> String {
+    let mut out = String::new();
+    let mut rest = raw;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let c = entity.and_then(|(entity, _)| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity.strip_prefix('#').and_then(|v| v.parse().ok()).and_then(char::from_u32),
+            },
+        });
+        match (c, entity) {
+            (Some(c), Some((_, end))) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+///Walks the input keeping track of the 1 based line and column, counted in characters like the parser does
+struct Cursor<'a> {
+    input: &'a str,
+    offset: usize,
+    line: u64,
+    column: u64,
+}
+
+impl<'a> Cursor<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.offset..]
+    }
+
+    fn take(&mut self, len: usize) -> &'a str {
+        let taken = &self.rest()[..len.min(self.rest().len())];
+        for c in taken.chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.offset += taken.len();
+        taken
+    }
+
+    ///Up to and including `end`, or the rest of the input if it isn't there
+    fn take_through(&mut self, end: &str) -> &'a str {
+        let len = self.rest().find(end).map_or(self.rest().len(), |i| i + end.len());
+        self.take(len)
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let len = self.rest().find(|c| !f(c)).unwrap_or(self.rest().len());
+        self.take(len)
+    }
+}
+
+///The nodes up to the closing tag of the element they're in or the end of the input. The input has been checked
+///to be well-formed so the scanning doesn't have to be strict
+fn parse_nodes(cursor: &mut Cursor) -> Vec<Node> {
+    let mut nodes = vec![];
+    while !cursor.rest().is_empty() && !cursor.rest().starts_with("</") {
+        let rest = cursor.rest();
+        let node = if rest.starts_with("<!--") {
+            Node::Other(cursor.take_through("-->").to_owned())
+        } else if rest.starts_with("<![CDATA[") {
+            Node::CData(cursor.take_through("]]>").to_owned())
+        } else if rest.starts_with("<?") {
+            Node::Other(cursor.take_through("?>").to_owned())
+        } else if rest.starts_with("<!") {
+            Node::Other(cursor.take_through(">").to_owned())
+        } else if rest.starts_with('<') {
+            let indent = match nodes.last() {
+                Some(Node::Text(raw)) => indent_of(raw),
+                _ => String::new(),
+            };
+            Node::Element(parse_element(cursor, indent))
+        } else {
+            Node::Text(cursor.take_while(|c| c != '<').to_owned())
+        };
+        nodes.push(node);
+    }
+    nodes
+}
+
+fn parse_element(cursor: &mut Cursor, indent: String) -> Element {
+    let start = Position {
+        line: cursor.line,
+        column: cursor.column,
+    };
+    cursor.take(1);
+    let mut element = Element::new(cursor.take_while(|c| !c.is_whitespace() && c != '/' && c != '>'));
+    element.start = start;
+    element.indent = indent;
+    loop {
+        let space = cursor.take_while(char::is_whitespace);
+        let rest = cursor.rest();
+        if rest.is_empty() || rest.starts_with('>') || rest.starts_with("/>") {
+            element.tag_end = space.to_owned();
+            element.self_closing = rest.starts_with("/>");
+            cursor.take_through(">");
+            break;
+        }
+        let name = cursor.take_while(|c| c != '=' && !c.is_whitespace());
+        let equals = cursor.take_while(|c| c != '"' && c != '\'');
+        let quote = cursor.take(1).chars().next().unwrap_or('"');
+        let value = cursor.take_while(|c| c != quote);
+        cursor.take(1);
+        element.attributes.push(Attribute {
+            space: space.to_owned(),
+            name: name.to_owned(),
+            equals: equals.to_owned(),
+            quote,
+            value: value.to_owned(),
+        });
+    }
+    if !element.self_closing {
+        element.children = parse_nodes(cursor);
+        element.close = cursor.take_through(">").to_owned();
+    }
+    element
+}
+
+///The white space an element is indented by given the text before it, nothing if it doesn't start a line
+fn indent_of(text: &str) -> String {
+    match text.rsplit_once('\n') {
+        Some((_, indent)) if indent.chars().all(char::is_whitespace) => indent.to_owned(),
+        _ => String::new(),
+    }
+}
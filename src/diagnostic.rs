@@ -0,0 +1,163 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::haml_parser::{Diagnostic, HamlError, ParseErr, Severity};
+use crate::Location;
+
+///A 1 based line and column in a HAML file, the same as [ParseErr] reports them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Position {
+    pub line: u64,
+    pub column: u64,
+}
+
+///The span a diagnostic covers. The parser only knows where an element starts so most ranges are empty i.e.
+///`start == end`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn at(line: u64, column: u64) -> Self {
+        let pos = Position { line, column };
+        Range { start: pos, end: pos }
+    }
+}
+
+impl From<&Location> for Range {
+    fn from(value: &Location) -> Self {
+        Range::at(value.line, value.column)
+    }
+}
+
+///Another place in the schema that explains a diagnostic e.g. the first declaration of a duplicate name
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RelatedLocation {
+    pub file: String,
+    pub range: Range,
+    pub message: String,
+}
+
+///A parse error or warning in a form tools can consume without pulling line/column back out of the string maps
+///[HttpError](rapid_utils::http_utils::HttpError) carries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HamlDiagnostic {
+    pub file: String,
+    pub range: Range,
+    pub code: String,
+    pub severity: Severity,
+    pub element: String,
+    pub message: String,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub suggestion: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub related: Vec<RelatedLocation>,
+}
+
+impl HamlDiagnostic {
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+
+    pub fn with_related(mut self, loc: &Location, message: impl Into<String>) -> Self {
+        self.related.push(RelatedLocation {
+            file: loc.file_name.clone(),
+            range: Range::from(loc),
+            message: message.into(),
+        });
+        self
+    }
+
+    ///The diagnostic as an LSP `Diagnostic`. LSP positions are 0 based so 1 is taken off the line and column,
+    ///the file isn't part of it, clients publish diagnostics per document.
+    #[cfg(feature = "serde")]
+    pub fn to_lsp(&self) -> serde_json::Value {
+        let related: Vec<serde_json::Value> = self
+            .related
+            .iter()
+            .map(|r| {
+                serde_json::json!({
+                    "location": {"uri": r.file, "range": lsp_range(&r.range)},
+                    "message": r.message,
+                })
+            })
+            .collect();
+        let mut value = serde_json::json!({
+            "range": lsp_range(&self.range),
+            "severity": match self.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            },
+            "code": self.code,
+            "source": "haml",
+            "message": self.message,
+            "data": {"element": self.element, "suggestion": self.suggestion},
+        });
+        if !related.is_empty() {
+            value["relatedInformation"] = serde_json::Value::Array(related);
+        }
+        value
+    }
+}
+
+#[cfg(feature = "serde")]
+fn lsp_range(range: &Range) -> serde_json::Value {
+    let pos = |p: &Position| serde_json::json!({"line": p.line.saturating_sub(1), "character": p.column.saturating_sub(1)});
+    serde_json::json!({"start": pos(&range.start), "end": pos(&range.end)})
+}
+
+impl From<&ParseErr> for HamlDiagnostic {
+    fn from(value: &ParseErr) -> Self {
+        HamlDiagnostic {
+            file: value.file.clone(),
+            range: Range::at(value.line, value.column),
+            code: value.code.to_string(),
+            severity: Severity::Error,
+            element: value.element.clone(),
+            message: value.message.clone(),
+            suggestion: None,
+            related: vec![],
+        }
+    }
+}
+
+impl From<&Diagnostic> for HamlDiagnostic {
+    fn from(value: &Diagnostic) -> Self {
+        HamlDiagnostic {
+            file: value.file.clone(),
+            range: Range::at(value.line, value.column),
+            code: value.code.to_string(),
+            severity: value.severity,
+            element: value.element.clone(),
+            message: value.message.clone(),
+            suggestion: None,
+            related: vec![],
+        }
+    }
+}
+
+///Semantic errors aren't tied to a place in a file so they get an empty file and range
+impl From<&HamlError> for HamlDiagnostic {
+    fn from(value: &HamlError) -> Self {
+        match value {
+            HamlError::ParseErr(e) => e.into(),
+            HamlError::Semantics { msg, code, .. } => HamlDiagnostic {
+                file: String::new(),
+                range: Range::default(),
+                code: code.to_string(),
+                severity: Severity::Error,
+                element: String::new(),
+                message: msg.clone(),
+                suggestion: None,
+                related: vec![],
+            },
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::str::FromStr;
+
+///The parsed form of a mapping's `from` attribute.
+///```text
+///$.input.user.email           - a field of the request input
+///steps.lookup.output.items[0] - a field of a step's output
+///${pipeline[0].field1}        - a variable such as pipeline, env or args
+///'active', 42, true, null     - literals
+///email                        - a field of the current value e.g. a column
+///```
+#[derive(Debug, Clone, PartialEq)]
+pub enum MappingExpr {
+    Literal(Literal),
+    Input(Vec<PathSegment>),
+    StepOutput { step: String, path: Vec<PathSegment> },
+    Variable { root: String, path: Vec<PathSegment> },
+    Field(Vec<PathSegment>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+impl FromStr for MappingExpr {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        parse_mapping(input).map_err(|e| format!("Invalid mapping expression '{}'. {}", input, e))
+    }
+}
+
+fn parse_mapping(input: &str) -> Result<MappingExpr, String> {
+    let expr = input.trim();
+    if expr.is_empty() {
+        return Err("The expression is empty".to_owned());
+    }
+    if let Some(literal) = parse_literal(expr)? {
+        return Ok(MappingExpr::Literal(literal));
+    }
+    if let Some(inner) = expr.strip_prefix("${") {
+        let inner = inner.strip_suffix('}').ok_or("'${' is never closed with '}'")?;
+        let mut path = parse_path(inner)?;
+        return match path.remove(0) {
+            PathSegment::Field(root) => Ok(MappingExpr::Variable { root, path }),
+            PathSegment::Index(_) => Err("A variable must start with a name".to_owned()),
+        };
+    }
+    if let Some(rest) = expr.strip_prefix('$') {
+        let rest = rest
+            .strip_prefix(".input")
+            .ok_or("Input expressions must start with $.input")?;
+        return match rest {
+            "" => Ok(MappingExpr::Input(vec![])),
+            _ => match rest.strip_prefix('.') {
+                Some(path) => Ok(MappingExpr::Input(parse_path(path)?)),
+                None => Err("Expected '.' after $.input".to_owned()),
+            },
+        };
+    }
+    let mut path = parse_path(expr)?;
+    if path.first() == Some(&PathSegment::Field("steps".to_owned())) {
+        return match (path.get(1), path.get(2)) {
+            (Some(PathSegment::Field(step)), Some(PathSegment::Field(output))) if output == "output" => {
+                let step = step.clone();
+                Ok(MappingExpr::StepOutput { step, path: path.split_off(3) })
+            }
+            _ => Err("Step expressions must be of the form steps.<id>.output".to_owned()),
+        };
+    }
+    Ok(MappingExpr::Field(path))
+}
+
+fn parse_literal(expr: &str) -> Result<Option<Literal>, String> {
+    for quote in ['\'', '"'] {
+        if let Some(rest) = expr.strip_prefix(quote) {
+            return match rest.strip_suffix(quote) {
+                Some(value) if !value.contains(quote) => Ok(Some(Literal::String(value.to_owned()))),
+                _ => Err(format!("The string literal must be closed with {}", quote)),
+            };
+        }
+    }
+    Ok(match expr {
+        "true" => Some(Literal::Bool(true)),
+        "false" => Some(Literal::Bool(false)),
+        "null" => Some(Literal::Null),
+        _ if expr.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+            Some(Literal::Number(expr.parse().map_err(|_| format!("'{}' is not a number", expr))?))
+        }
+        _ => None,
+    })
+}
+
+///Parses `a.b[0].c` into its segments
+fn parse_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let mut segments = vec![];
+    for part in path.trim().split('.') {
+        let (name, mut indices) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if !is_identifier(name) {
+            return Err(format!("'{}' is not a valid field name", name));
+        }
+        segments.push(PathSegment::Field(name.to_owned()));
+        while !indices.is_empty() {
+            let (index, rest) = indices
+                .strip_prefix('[')
+                .and_then(|v| v.split_once(']'))
+                .ok_or_else(|| format!("'{}' has an unclosed '['", part))?;
+            segments.push(PathSegment::Index(
+                index.trim().parse().map_err(|_| format!("'{}' is not a valid index", index))?,
+            ));
+            indices = rest;
+        }
+    }
+    Ok(segments)
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+///The parsed form of a response's `when` attribute, a condition that can be wrapped in `${}`.
+///```text
+///${pipeline[0].success == true}        - comparisons ==, !=, <, <=, > and >=
+///${created && !(steps.a.output.n > 1)} - logical operators &&, || and !
+///${created and not (a or b)}           - and, or and not, which don't need escaping in XML
+///${len(pipeline) >= 2}                 - calls to functions the executor provides
+///```
+///Operands are written like a mapping's from expression except a bare field path is a variable, e.g. `created`
+///is the variable `${created}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Value(MappingExpr),
+    Call { name: String, args: Vec<Condition> },
+    Not(Box<Condition>),
+    Compare { op: CompareOp, left: Box<Condition>, right: Box<Condition> },
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let err = |reason: &str| format!("Invalid when expression '{}'. {}", input, reason);
+        let mut expr = input.trim();
+        //`${...}` wraps the whole condition unless the braces close before the end e.g. `${a} == ${b}`
+        if let Some(inner) = expr.strip_prefix("${").and_then(|v| v.strip_suffix('}')) {
+            if !inner.contains(['{', '}']) {
+                expr = inner;
+            }
+        }
+        let tokens = tokenize(expr).map_err(|e| err(&e))?;
+        if tokens.is_empty() {
+            return Err(err("The expression is empty"));
+        }
+        let mut parser = ConditionParser { tokens, pos: 0 };
+        let condition = parser.or().map_err(|e| err(&e))?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(condition),
+            Some(token) => Err(err(&format!("Unexpected '{}'", token))),
+        }
+    }
+}
+
+///Splits a condition into operators, parentheses, commas and operands, keeping quoted strings whole
+fn tokenize(expr: &str) -> Result<Vec<String>, String> {
+    let is_operand = |c: char| c.is_alphanumeric() || "_.[]$-{}".contains(c);
+    let mut tokens = vec![];
+    let mut chars = expr.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        match c {
+            '(' | ')' | ',' => {}
+            '=' | '!' | '<' | '>' => {
+                if let Some((i, '=')) = chars.peek() {
+                    end = i + 1;
+                    chars.next();
+                } else if c == '=' {
+                    return Err("Use '==' to compare values".to_owned());
+                }
+            }
+            '&' | '|' => match chars.next() {
+                Some((i, next)) if next == c => end = i + 1,
+                _ => return Err(format!("Use '{}{}' to combine conditions", c, c)),
+            },
+            '\'' | '"' => {
+                let (i, _) = chars
+                    .find(|(_, next)| *next == c)
+                    .ok_or_else(|| format!("The string literal must be closed with {}", c))?;
+                end = i + 1;
+            }
+            _ if is_operand(c) => {
+                while let Some(&(i, next)) = chars.peek() {
+                    if !is_operand(next) {
+                        break;
+                    }
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+            }
+            _ => return Err(format!("'{}' is not allowed in a condition", c)),
+        }
+        tokens.push(expr[start..end].to_owned());
+    }
+    Ok(tokens)
+}
+
+///A recursive descent parser, from lowest to highest precedence `||`, `&&`, `!`, comparisons then operands
+struct ConditionParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl ConditionParser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|v| v.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("The expression ends unexpectedly")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("Expected '{}' but found '{}'", expected, token)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut left = self.and()?;
+        while matches!(self.peek(), Some("||" | "or")) {
+            self.pos += 1;
+            left = Condition::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut left = self.not()?;
+        while matches!(self.peek(), Some("&&" | "and")) {
+            self.pos += 1;
+            left = Condition::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        if matches!(self.peek(), Some("!" | "not")) {
+            self.pos += 1;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Condition, String> {
+        let left = self.operand()?;
+        let op = match self.peek() {
+            Some("==") => CompareOp::Eq,
+            Some("!=") => CompareOp::Ne,
+            Some("<") => CompareOp::Lt,
+            Some("<=") => CompareOp::Le,
+            Some(">") => CompareOp::Gt,
+            Some(">=") => CompareOp::Ge,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.operand()?;
+        Ok(Condition::Compare { op, left: Box::new(left), right: Box::new(right) })
+    }
+
+    fn operand(&mut self) -> Result<Condition, String> {
+        let token = self.next()?;
+        if token == "(" {
+            let condition = self.or()?;
+            self.expect(")")?;
+            return Ok(condition);
+        }
+        if !token.starts_with(|c: char| c.is_alphanumeric() || "_$-'\"".contains(c)) {
+            return Err(format!("Expected a value but found '{}'", token));
+        }
+        if self.peek() == Some("(") {
+            if !is_identifier(&token) {
+                return Err(format!("'{}' is not a valid function name", token));
+            }
+            self.pos += 1;
+            let mut args = vec![];
+            if self.peek() == Some(")") {
+                self.pos += 1;
+            } else {
+                loop {
+                    args.push(self.or()?);
+                    match self.next()?.as_str() {
+                        "," => {}
+                        ")" => break,
+                        token => return Err(format!("Expected ',' or ')' but found '{}'", token)),
+                    }
+                }
+            }
+            return Ok(Condition::Call { name: token, args });
+        }
+        let value = match parse_mapping(&token)? {
+            MappingExpr::Field(mut path) => match path.remove(0) {
+                PathSegment::Field(root) => MappingExpr::Variable { root, path },
+                PathSegment::Index(_) => return Err(format!("'{}' must start with a name", token)),
+            },
+            value => value,
+        };
+        Ok(Condition::Value(value))
+    }
+}
+
+///The parsed form of a response's `yield` attribute, which of the pipeline's results the response returns.
+///```text
+///0     - the object at index 0 instead of an array
+///5..   - the items from index 5 to the end
+///5..11 - the items from index 5 up to but not including 11
+///..6   - the first 6 items
+///```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YieldRange {
+    Index(usize),
+    Range { start: Option<usize>, end: Option<usize> },
+}
+
+impl FromStr for YieldRange {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let err = |reason: &str| format!("Invalid yield '{}'. {}", input, reason);
+        let index = |value: &str| -> Result<Option<usize>, String> {
+            match value.trim() {
+                "" => Ok(None),
+                value => value
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| err(&format!("'{}' is not a valid index", value))),
+            }
+        };
+        let (start, end) = match input.split_once("..") {
+            None => return index(input)?.map(YieldRange::Index).ok_or_else(|| err("The yield is empty")),
+            Some((start, end)) => (index(start)?, index(end)?),
+        };
+        match (start, end) {
+            (None, None) => Err(err("A range needs a start, an end or both")),
+            (Some(start), Some(end)) if start > end => Err(err("The start of the range is after its end")),
+            _ => Ok(YieldRange::Range { start, end }),
+        }
+    }
+}
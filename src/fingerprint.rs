@@ -0,0 +1,380 @@
+use crate::expression::{CompareOp, Condition, Literal, MappingExpr, PathSegment, YieldRange};
+use crate::haml_parser::{ColumnDefault, ColumnType, GraphQLOperation, WellKnownType};
+use crate::manifested_schema::{
+    ApiVersionDef, CacheDef, CallStep, CallTarget, ColumnDef, ColumnPipeline, ConstraintDef, DatabaseDef, DockerStep,
+    EndpointDef, EnvVar, ErrorMappingDef, FeatureDef, FederationKeyDef, FnStep, GraphQLApiDef, HypiDef, JobDef, Mapping,
+    MetaDef, Middleware, MiddlewareDef, PairDef, Pipeline, PipelineField, ResolverDef, ResponseDef, RestApiDef,
+    SchemaDef, ScriptStep, SearchIndexDef, SqlStep, Step, TableDef,
+};
+use crate::template::TemplateEngine;
+use crate::{
+    BuildArg, ColumnEncryption, ColumnValidation, ConstraintViolationAction, CoreApi, CredentialRef, DatabaseType,
+    DbReplica, DbTlsConfig, DockerConnectionInfo, DockerHealthcheck, DockerPort, DockerResources, DockerStepProvider,
+    DockerVolume, EncryptionAlgorithm, HamlVersion, HashAlgorithm, HttpStatus, ImplicitDockerStepPosition, LockoutPolicy,
+    LogFormat, LogLevel, LogMode, LogPolicy, LoggingConfig, MaskPolicy, MappingTransform, MediaType, MessageProvider,
+    MessageProviderKind, MetricsConfig, ObservabilityConfig, PoolConfig, PullPolicy, ReadPreference, RemoteTls,
+    RequestLimits, ScriptLimits, ScriptType, SslMode, StepPlacement, StorageConfig, StorageProvider,
+    TableConstraintType, TotpConfig, TracingConfig, ValidationMode,
+};
+use rapid_utils::http_utils::HttpMethod;
+use std::collections::HashMap;
+use std::time::Duration;
+
+///64 bit FNV-1a, used instead of std's hashers whose output can change between Rust releases
+pub(crate) struct Fingerprinter(u64);
+
+impl Default for Fingerprinter {
+    fn default() -> Self {
+        Fingerprinter(0xcbf29ce484222325)
+    }
+}
+
+impl Fingerprinter {
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    ///Strings are length prefixed so `("ab", "c")` and `("a", "bc")` hash differently
+    fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write(value.as_bytes());
+    }
+}
+
+///Feeds what a definition declares into a [Fingerprinter], field by field. Locations are never hashed so moving
+///a declaration around a file, or into another file, doesn't change the fingerprint.
+pub(crate) trait Fingerprint {
+    fn fingerprint_into(&self, f: &mut Fingerprinter);
+}
+
+macro_rules! fingerprint_ints {
+    ($($ty:ty),*) => {
+        $(impl Fingerprint for $ty {
+            fn fingerprint_into(&self, f: &mut Fingerprinter) {
+                f.write_u64(*self as u64);
+            }
+        })*
+    };
+}
+
+fingerprint_ints!(u8, u16, u32, u64, usize);
+
+impl Fingerprint for bool {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        f.write(&[*self as u8]);
+    }
+}
+
+impl Fingerprint for f64 {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        f.write_u64(self.to_bits());
+    }
+}
+
+impl Fingerprint for String {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        f.write_str(self);
+    }
+}
+
+impl Fingerprint for Duration {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        f.write_u64(self.as_secs());
+        f.write_u64(self.subsec_nanos() as u64);
+    }
+}
+
+impl<T: Fingerprint> Fingerprint for Option<T> {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        match self {
+            None => f.write(&[0]),
+            Some(v) => {
+                f.write(&[1]);
+                v.fingerprint_into(f);
+            }
+        }
+    }
+}
+
+impl<T: Fingerprint> Fingerprint for Box<T> {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        self.as_ref().fingerprint_into(f);
+    }
+}
+
+impl<T: Fingerprint> Fingerprint for Vec<T> {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        f.write_u64(self.len() as u64);
+        for v in self {
+            v.fingerprint_into(f);
+        }
+    }
+}
+
+impl<A: Fingerprint, B: Fingerprint> Fingerprint for (A, B) {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        self.0.fingerprint_into(f);
+        self.1.fingerprint_into(f);
+    }
+}
+
+///Entries are hashed on their own and combined in sorted order since a HashMap iterates in a different order each run
+impl<K: Fingerprint, V: Fingerprint> Fingerprint for HashMap<K, V> {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        let mut entries: Vec<_> = self
+            .iter()
+            .map(|(key, value)| {
+                let mut entry = Fingerprinter::default();
+                key.fingerprint_into(&mut entry);
+                value.fingerprint_into(&mut entry);
+                entry.finish()
+            })
+            .collect();
+        entries.sort_unstable();
+        entries.fingerprint_into(f);
+    }
+}
+
+impl Fingerprint for HttpMethod {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        //the method's name, the same way endpoint keys are built from it
+        f.write_str(&format!("{:?}", self).to_uppercase());
+    }
+}
+
+impl Fingerprint for HttpStatus {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        self.0.fingerprint_into(f);
+    }
+}
+
+impl Fingerprint for Condition {
+    fn fingerprint_into(&self, f: &mut Fingerprinter) {
+        match self {
+            Condition::Value(expr) => {
+                f.write_str("Value");
+                expr.fingerprint_into(f);
+            }
+            Condition::Call { name, args } => {
+                f.write_str("Call");
+                name.fingerprint_into(f);
+                args.fingerprint_into(f);
+            }
+            Condition::Not(condition) => {
+                f.write_str("Not");
+                condition.fingerprint_into(f);
+            }
+            Condition::Compare { op, left, right } => {
+                f.write_str("Compare");
+                op.fingerprint_into(f);
+                left.fingerprint_into(f);
+                right.fingerprint_into(f);
+            }
+            Condition::And(left, right) => {
+                f.write_str("And");
+                left.fingerprint_into(f);
+                right.fingerprint_into(f);
+            }
+            Condition::Or(left, right) => {
+                f.write_str("Or");
+                left.fingerprint_into(f);
+                right.fingerprint_into(f);
+            }
+        }
+    }
+}
+
+///Hashes the type's name then each listed field with its name. The fields are destructured without `..` so adding a
+///field to a definition fails to compile until it is listed here, or under `skip` if it is a location.
+macro_rules! fingerprint_struct {
+    ($($ty:ident { $($field:ident),* $(,)? $(; skip $($skip:ident),* $(,)?)? })*) => {
+        $(impl Fingerprint for $ty {
+            fn fingerprint_into(&self, f: &mut Fingerprinter) {
+                let $ty { $($field,)* $($($skip: _,)*)? } = self;
+                f.write_str(stringify!($ty));
+                $(
+                    f.write_str(stringify!($field));
+                    $field.fingerprint_into(f);
+                )*
+            }
+        })*
+    };
+}
+
+///Hashes the variant's name then its fields, for enums whose variants are all units, one field tuples or structs
+macro_rules! fingerprint_enum {
+    ($($ty:ident {
+        $($variant:ident $(($value:ident))? $({ $($field:ident),* $(,)? })?),* $(,)?
+    })*) => {
+        $(impl Fingerprint for $ty {
+            fn fingerprint_into(&self, f: &mut Fingerprinter) {
+                match self {
+                    $($ty::$variant $(($value))? $({ $($field),* })? => {
+                        f.write_str(stringify!($variant));
+                        $($value.fingerprint_into(f);)?
+                        $($(
+                            f.write_str(stringify!($field));
+                            $field.fingerprint_into(f);
+                        )*)?
+                    })*
+                }
+            }
+        })*
+    };
+}
+
+fingerprint_struct! {
+    MessageProvider { kind, provider, credentials, from, template_dir; skip start_pos, end_pos }
+    TotpConfig { issuer, digits, period, drift }
+    LockoutPolicy { max_attempts, window, cooldown; skip start_pos, end_pos }
+    RestApiDef { base, endpoints, versions; skip start_pos, end_pos }
+    EndpointDef {
+        method, path, name, public, accepts, produces, pipeline, request, responses, error_mappings, validation,
+        deprecated, middleware, cache, limits, log, feature, version, doc;
+        skip start_pos, end_pos
+    }
+    MediaType { typ, subtype, params }
+    Pipeline {
+        name, label, steps, input, output, skip_implicit_steps, is_async, deprecated, feature;
+        skip start_pos, end_pos
+    }
+    DockerStep {
+        name, provider, mappings, placement, env, ports, volumes, resources, pull_policy, healthcheck, implicit, log;
+        skip start_pos, end_pos
+    }
+    DockerConnectionInfo {
+        username, password, image, tag, digest, healthcheck, credentials, build_args, context, cache;
+        skip start_pos, end_pos
+    }
+    DockerHealthcheck { command, path, port, interval, timeout, retries; skip start_pos, end_pos }
+    BuildArg { name, value; skip start_pos, end_pos }
+    RemoteTls { ca_cert, client_cert, client_key }
+    Mapping { from, expr, to, typ, children, readable_by, writable_by, transforms; skip start_pos, end_pos }
+    StepPlacement { before, after }
+    EnvVar { name, value; skip start_pos, end_pos }
+    DockerPort { container, host, protocol; skip start_pos, end_pos }
+    DockerVolume { source, target, read_only; skip start_pos, end_pos }
+    DockerResources { cpus, memory; skip start_pos, end_pos }
+    LogPolicy { mode, redact_fields }
+    SqlStep { name, db, sql, import, parameters, mappings, cache, log; skip start_pos, end_pos }
+    CacheDef { ttl, key, vary; skip start_pos }
+    ScriptStep { name, lang, source, import, limits, mappings, log; skip start_pos, end_pos }
+    ScriptLimits { memory, cpu_ms, allow_net }
+    FnStep { name, version, mappings, log; skip start_pos, end_pos }
+    CallStep { name, target, mappings, log; skip start_pos, end_pos }
+    PipelineField { name, typ, required; skip start_pos }
+    ResponseDef {
+        status, when, condition, is_default, yield_expr, yield_range, body, template, mappings;
+        skip start_pos, end_pos
+    }
+    ErrorMappingDef { code, status, template, body; skip start_pos, end_pos }
+    MiddlewareDef { name, runs; skip start_pos, end_pos }
+    RequestLimits { max_body_size, max_files }
+    ApiVersionDef { name, base, deprecates, sunset; skip start_pos, end_pos }
+    GraphQLApiDef {
+        base, from, enable_subscriptions, resolvers, expose_as_subgraph, federation_keys;
+        skip start_pos, end_pos
+    }
+    ResolverDef { typ, field, pipeline; skip start_pos, end_pos }
+    FederationKeyDef { table, key; skip start_pos, end_pos }
+    JobDef {
+        name, pipeline, start, end, interval, interval_frequency, enabled, repeats, feature;
+        skip start_pos, end_pos
+    }
+    FeatureDef { name, default; skip start_pos, end_pos }
+    DatabaseDef {
+        name, typ, username, password, db_name, host, port, file, pool, tls, read_preference, replicas, url, schemas;
+        skip start_pos, end_pos
+    }
+    PoolConfig { max_connections, min_connections, connect_timeout, idle_timeout }
+    DbTlsConfig { ssl_mode, ca_cert, client_cert, client_key }
+    DbReplica { host, port; skip start_pos, end_pos }
+    SchemaDef { name, tables }
+    TableDef { name, columns, constraints, hypi, search_indexes, doc; skip start_pos, end_pos }
+    ColumnDef {
+        name, typ, nullable, unique, default, primary_key, pipeline, readable_by, writable_by, validation, sensitive,
+        mask, encryption, deprecated, doc;
+        skip start_pos, end_pos
+    }
+    ColumnPipeline {
+        args, write, read;
+        skip args_start_pos, args_end_pos, write_start_pos, write_end_pos, read_start_pos, read_end_pos
+    }
+    ColumnValidation { min, max, pattern, min_length, max_length }
+    ColumnEncryption { algorithm, key_ref }
+    ConstraintDef { name, columns, typ, mappings; skip start_pos, end_pos }
+    HypiDef { well_known, mappings; skip start_pos, end_pos }
+    SearchIndexDef { name, columns, language; skip start_pos, end_pos }
+    MetaDef { pairs; skip start_pos, end_pos }
+    PairDef { key, value; skip start_pos, end_pos }
+    HamlVersion { major, minor }
+    StorageConfig {
+        provider, bucket, region, path, credentials, max_upload_size, content_types;
+        skip start_pos, end_pos
+    }
+    ObservabilityConfig { tracing, metrics, logging; skip start_pos, end_pos }
+    TracingConfig { endpoint_env, sample_rate; skip start_pos, end_pos }
+    MetricsConfig { enabled, prefix; skip start_pos, end_pos }
+    LoggingConfig { level, format; skip start_pos, end_pos }
+}
+
+fingerprint_enum! {
+    CoreApi {
+        Register, LoginByEmail, LoginByUsername, OAuth, PasswordResetTrigger, PasswordReset, MagicLink,
+        TwoFactorAuthEmail, TwoFactorAuthSms, TwoFactorStep2, TwoFactorTotp, VerifyAccount,
+    }
+    MessageProviderKind { Sms, Email }
+    CredentialRef { Env(name), Secret(name), Inline { username, password } }
+    Step { Docker(step), Sql(step), Script(step), Fn(step), Call(step) }
+    DockerStepProvider {
+        Custom { name, path },
+        Dockerfile { path },
+        DockerImage(info),
+        Remote { host, port, tls, auth_token },
+    }
+    MappingExpr {
+        Literal(value),
+        Input(path),
+        StepOutput { step, path },
+        Variable { root, path },
+        Field(path),
+    }
+    Literal { String(value), Number(value), Bool(value), Null }
+    PathSegment { Field(name), Index(index) }
+    ColumnType { TEXT, INT, BIGINT, FLOAT, DOUBLE, TIMESTAMP, BOOL, BYTEA }
+    ColumnDefault { UniqueSqid, UniqueUlid, UniqueSnowflake }
+    MappingTransform { Lowercase, Uppercase, Trim, Hash(algorithm), Custom(name) }
+    HashAlgorithm { Bcrypt, Argon2, Sha256 }
+    ImplicitDockerStepPosition { First, Each, Last, Step(name) }
+    PullPolicy { Always, IfNotPresent, Never }
+    LogMode { None, Metadata, Full }
+    ScriptType { JavaScript, TypeScript, Python, Lua }
+    CallTarget { Endpoint { name, method }, Pipeline(name), CoreApi(api) }
+    CompareOp { Eq, Ne, Lt, Le, Gt, Ge }
+    YieldRange { Index(index), Range { start, end } }
+    TemplateEngine { Handlebars, JsonPath, Liquid }
+    ValidationMode { Strict, Lenient, Off }
+    Middleware { Pipeline(name), Step(step) }
+    GraphQLOperation { Query, Mutation, Subscription }
+    DatabaseType { MekaDb, Postgres, MySQL, MariaDB, Oracle, MsSql, Sqlite, CockroachDB }
+    SslMode { Disable, Require, VerifyFull }
+    ReadPreference { Primary, ReplicaPreferred, Replica }
+    MaskPolicy { Last4, Hash, Redact }
+    EncryptionAlgorithm { AesGcm }
+    TableConstraintType { ForeignKey { on_delete, on_update }, Unique }
+    ConstraintViolationAction { Cascade, Restrict }
+    WellKnownType { Account, File, Permission, Role, Organization, Tenant }
+    StorageProvider { S3, Gcs, Local }
+    LogLevel { Trace, Debug, Info, Warn, Error }
+    LogFormat { Text, Json }
+}
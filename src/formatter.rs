@@ -0,0 +1,221 @@
+use xml::common::Position;
+use xml::name::OwnedName;
+use xml::reader::{ParserConfig, XmlEvent};
+
+use crate::haml_parser::{xml_error, Result};
+
+///Controls how [format] lays out a document
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    ///Spaces per level of nesting
+    pub indent: usize,
+    ///Sort the tables in each schema by name
+    pub sort_tables: bool,
+    ///Sort the columns in each table by name
+    pub sort_columns: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent: 4,
+            sort_tables: false,
+            sort_columns: false,
+        }
+    }
+}
+
+enum Node {
+    Element(Element),
+    Text(String),
+    CData(String),
+    Comment(String),
+    ///One or more blank lines in the input, kept as a single blank line
+    BlankLine,
+}
+
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+///Re-emits a HAML document in its canonical form. Each element is on its own line, indented by its depth,
+///with its `name` attribute first and the rest in alphabetical order. Comments are kept and runs of blank
+///lines are collapsed into one. Sorted elements keep the comments written above them.
+pub fn format(input: &str, options: &FormatOptions) -> Result<String> {
+    let mut nodes = parse(input)?;
+    for node in &mut nodes {
+        if let Node::Element(element) = node {
+            sort(element, options);
+        }
+    }
+    let mut out = String::new();
+    if input.trim_start().starts_with("<?xml") {
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    }
+    write_nodes(&mut out, &nodes, 0, options);
+    Ok(out)
+}
+
+///True if `input` is already formatted, for use as a CI formatting gate
+pub fn check(input: &str, options: &FormatOptions) -> Result<bool> {
+    Ok(format(input, options)? == input)
+}
+
+fn parse(input: &str) -> Result<Vec<Node>> {
+    let mut reader = ParserConfig::new()
+        .ignore_comments(false)
+        .create_reader(input.as_bytes());
+    //the bottom of the stack holds the nodes outside the root element
+    let mut stack = vec![Element {
+        name: String::new(),
+        attributes: vec![],
+        children: vec![],
+    }];
+    loop {
+        let node = match reader.next() {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                stack.push(Element {
+                    name: qualified_name(&name),
+                    attributes: attributes
+                        .into_iter()
+                        .map(|v| (qualified_name(&v.name), v.value))
+                        .collect(),
+                    children: vec![],
+                });
+                continue;
+            }
+            Ok(XmlEvent::EndElement { .. }) => match stack.pop() {
+                Some(element) => Node::Element(element),
+                None => continue,
+            },
+            Ok(XmlEvent::Characters(text)) => Node::Text(text.trim().to_owned()),
+            Ok(XmlEvent::CData(text)) => Node::CData(text),
+            Ok(XmlEvent::Comment(text)) => Node::Comment(text),
+            Ok(XmlEvent::Whitespace(text)) if text.matches('\n').count() > 1 => Node::BlankLine,
+            Ok(XmlEvent::EndDocument) => break,
+            Ok(_) => continue,
+            Err(e) => return Err(xml_error("", &e, reader.position())),
+        };
+        if let Some(parent) = stack.last_mut() {
+            parent.children.push(node);
+        }
+    }
+    Ok(stack.pop().map(|v| v.children).unwrap_or_default())
+}
+
+fn qualified_name(name: &OwnedName) -> String {
+    match &name.prefix {
+        Some(prefix) => format!("{}:{}", prefix, name.local_name),
+        None => name.local_name.clone(),
+    }
+}
+
+fn sort(element: &mut Element, options: &FormatOptions) {
+    if options.sort_tables && element.name == "schema" {
+        sort_elements(&mut element.children, "table");
+    }
+    if options.sort_columns && element.name == "table" {
+        sort_elements(&mut element.children, "column");
+    }
+    for child in &mut element.children {
+        if let Node::Element(child) = child {
+            sort(child, options);
+        }
+    }
+}
+
+///Sorts the `name` elements in `children` by their name attribute. Other elements stay where they are and
+///each sorted element takes the comments and blank lines before it along.
+fn sort_elements(children: &mut Vec<Node>, name: &str) {
+    let mut groups: Vec<Vec<Node>> = vec![];
+    let mut pending = vec![];
+    for node in children.drain(..) {
+        let is_element = matches!(node, Node::Element(_));
+        pending.push(node);
+        if is_element {
+            groups.push(std::mem::take(&mut pending));
+        }
+    }
+    let key = |group: &Vec<Node>| match group.last() {
+        Some(Node::Element(element)) if element.name == name => Some(attribute(element, "name").to_owned()),
+        _ => None,
+    };
+    //taking the sorted groups out leaves empty slots to put them back into in order
+    let mut sorted: Vec<Vec<Node>> = groups
+        .iter_mut()
+        .filter(|v| key(v).is_some())
+        .map(std::mem::take)
+        .collect();
+    sorted.sort_by_key(|v| key(v));
+    let mut sorted = sorted.into_iter();
+    for group in &mut groups {
+        if group.is_empty() {
+            *group = sorted.next().unwrap_or_default();
+        }
+    }
+    children.extend(groups.into_iter().flatten());
+    children.extend(pending);
+}
+
+fn attribute<'a>(element: &'a Element, name: &str) -> &'a str {
+    element
+        .attributes
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+        .unwrap_or("")
+}
+
+fn write_nodes(out: &mut String, nodes: &[Node], depth: usize, options: &FormatOptions) {
+    for (i, node) in nodes.iter().enumerate() {
+        let skip = match node {
+            Node::BlankLine => i == 0 || i == nodes.len() - 1 || matches!(nodes[i - 1], Node::BlankLine),
+            Node::Text(text) => text.is_empty(),
+            _ => false,
+        };
+        if !skip {
+            write_node(out, node, depth, options);
+        }
+    }
+}
+
+fn write_node(out: &mut String, node: &Node, depth: usize, options: &FormatOptions) {
+    let indent = " ".repeat(depth * options.indent);
+    match node {
+        Node::Element(element) => {
+            out.push_str(&indent);
+            out.push('<');
+            out.push_str(&element.name);
+            let mut attributes: Vec<_> = element.attributes.iter().collect();
+            attributes.sort_by_key(|(name, _)| (name != "name", name.as_str()));
+            for (name, value) in attributes {
+                out.push_str(&format!(" {}=\"{}\"", name, escape(value, true)));
+            }
+            match element.children.as_slice() {
+                [] => out.push_str("/>\n"),
+                [Node::Text(text)] => out.push_str(&format!(">{}</{}>\n", escape(text, false), element.name)),
+                [Node::CData(text)] => out.push_str(&format!("><![CDATA[{}]]></{}>\n", text, element.name)),
+                children => {
+                    out.push_str(">\n");
+                    write_nodes(out, children, depth + 1, options);
+                    out.push_str(&format!("{}</{}>\n", indent, element.name));
+                }
+            }
+        }
+        Node::Text(text) => out.push_str(&format!("{}{}\n", indent, escape(text, false))),
+        Node::CData(text) => out.push_str(&format!("{}<![CDATA[{}]]>\n", indent, text)),
+        Node::Comment(text) => out.push_str(&format!("{}<!--{}-->\n", indent, text)),
+        Node::BlankLine => out.push('\n'),
+    }
+}
+
+pub(crate) fn escape(value: &str, attribute: bool) -> String {
+    let value = value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    if attribute {
+        value.replace('"', "&quot;")
+    } else {
+        value
+    }
+}
@@ -0,0 +1,40 @@
+use std::fmt::{Debug, Formatter};
+
+///Describes a function `<fn>` steps can call, see [FnRegistry]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FnSignature {
+    pub name: String,
+    pub version: String,
+    pub parameters: Vec<FnParameter>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FnParameter {
+    pub name: String,
+    ///A required parameter must be bound by one of the step's mappings
+    pub required: bool,
+}
+
+///The functions available to `<fn name="..." version="...">` steps. Given to the parser with
+///[ParseOptions::fn_registry](crate::haml_parser::ParseOptions::fn_registry) so a step calling a function that
+///doesn't exist, or mapping values to parameters it doesn't have, fails when the document is parsed.
+pub trait FnRegistry {
+    ///The function called `name` at `version`, or the version steps that don't give one use
+    fn find(&self, name: &str, version: Option<&str>) -> Option<FnSignature>;
+}
+
+impl Debug for dyn FnRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FnRegistry")
+    }
+}
+
+///A fixed list of functions. Steps that don't give a version use the last one listed with the function's name
+impl FnRegistry for Vec<FnSignature> {
+    fn find(&self, name: &str, version: Option<&str>) -> Option<FnSignature> {
+        self.iter()
+            .rev()
+            .find(|v| v.name == name && version.map(|version| v.version == version).unwrap_or(true))
+            .cloned()
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{Display, Formatter};
+
+use crate::manifested_schema::{endpoint_key, DocumentDef, Mapping, TableDef};
+use crate::{TableConstraintType, TableRef};
+
+///Something in a document other things can depend on
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Entity {
+    ///An endpoint identified by its upper case method and path e.g. `GET account/{id}`
+    Endpoint(String),
+    Job(String),
+    Pipeline(String),
+    ///A table's fully qualified name, `db.schema.table`
+    Table(String),
+    Column { table: String, column: String },
+}
+
+impl Entity {
+    ///The table this entity is or is a column of
+    fn table(&self) -> Option<&str> {
+        match self {
+            Entity::Table(table) | Entity::Column { table, .. } => Some(table),
+            _ => None,
+        }
+    }
+}
+
+///`from` depends on `to` e.g. an endpoint on the pipeline it calls
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Dependency {
+    pub from: Entity,
+    pub to: Entity,
+}
+
+///Which entities of a document depend on which, for impact analysis like what breaks if a column is dropped.
+///```text
+///endpoint, job -> pipeline   the pipeline it calls
+///pipeline      -> column     a step mapping reads or writes table.column
+///column        -> column     a foreign key from one table's column to another's
+///```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependencyGraph {
+    pub edges: Vec<Dependency>,
+}
+
+impl DependencyGraph {
+    ///What depends directly on `entity`. The dependents of a table include the dependents of its columns
+    pub fn dependents(&self, entity: &Entity) -> Vec<&Entity> {
+        let depends_on = |to: &Entity| match entity {
+            Entity::Table(_) => to.table() == entity.table(),
+            _ => to == entity,
+        };
+        self.edges.iter().filter(|v| depends_on(&v.to)).map(|v| &v.from).collect()
+    }
+
+    ///What `entity` depends on directly
+    pub fn dependencies(&self, entity: &Entity) -> Vec<&Entity> {
+        self.edges.iter().filter(|v| &v.from == entity).map(|v| &v.to).collect()
+    }
+
+    ///Everything that depends on `entity` directly or through something else, e.g. a column, the pipelines that
+    ///map it and the endpoints that call those pipelines
+    pub fn impacted_by(&self, entity: &Entity) -> Vec<&Entity> {
+        let mut seen = HashSet::new();
+        let mut impacted = vec![];
+        let mut pending = self.dependents(entity);
+        while let Some(next) = pending.pop() {
+            if next != entity && seen.insert(next) {
+                impacted.push(next);
+                pending.extend(self.dependents(next));
+            }
+        }
+        impacted
+    }
+}
+
+impl From<&DocumentDef> for DependencyGraph {
+    fn from(doc: &DocumentDef) -> Self {
+        //a set keeps the edges sorted and drops the duplicates mappings produce
+        let mut edges = BTreeSet::new();
+        for endpoint in doc.endpoints() {
+            edges.insert(Dependency {
+                from: Entity::Endpoint(endpoint_key(&endpoint.method, endpoint.path.as_deref().unwrap_or(""))),
+                to: Entity::Pipeline(endpoint.pipeline.name.clone()),
+            });
+        }
+        for job in &doc.jobs {
+            edges.insert(Dependency {
+                from: Entity::Job(job.name.clone()),
+                to: Entity::Pipeline(job.pipeline.clone()),
+            });
+        }
+        let endpoint_pipelines = doc.endpoints().map(|v| &v.pipeline);
+        for pipeline in doc.pipelines.iter().chain(endpoint_pipelines) {
+            let mut mappings = vec![];
+            pipeline.steps.iter().for_each(|v| flatten(v.mappings(), &mut mappings));
+            for reference in mappings.iter().flat_map(|v| [Some(&v.from), v.to.as_ref()]).flatten() {
+                if let Some(column) = resolve_column(doc, None, reference) {
+                    edges.insert(Dependency {
+                        from: Entity::Pipeline(pipeline.name.clone()),
+                        to: column,
+                    });
+                }
+            }
+        }
+        for db in &doc.databases {
+            for schema in &db.schemas {
+                for table in &schema.tables {
+                    let table_ref = TableRef {
+                        db: db.name.clone(),
+                        schema: schema.name.clone(),
+                        table: table.name.clone(),
+                    };
+                    let foreign_keys = table
+                        .constraints
+                        .iter()
+                        .filter(|v| matches!(v.typ, TableConstraintType::ForeignKey { .. }));
+                    for mapping in foreign_keys.flat_map(|v| v.mappings.iter()) {
+                        if let Some(to) = mapping.to.as_ref().and_then(|v| resolve_column(doc, Some(&table_ref), v)) {
+                            edges.insert(Dependency {
+                                from: Entity::Column {
+                                    table: table_ref.qualified_name(),
+                                    column: mapping.from.clone(),
+                                },
+                                to,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        DependencyGraph {
+            edges: edges.into_iter().collect(),
+        }
+    }
+}
+
+///Tables that reference each other through foreign keys, each table references the next and the last the first
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableCycle {
+    ///The fully qualified name of each table in the cycle
+    pub tables: Vec<String>,
+}
+
+impl Display for TableCycle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The foreign keys of these tables form a cycle: {}", self.tables.join(" -> "))?;
+        match self.tables.first() {
+            Some(first) => write!(f, " -> {}", first),
+            None => Ok(()),
+        }
+    }
+}
+
+///Every table in the document ordered so a table comes after the tables its foreign keys reference, the order to
+///create tables or seed data in. Tables that don't depend on each other keep the order they're declared in and
+///a table referencing itself is allowed. Returns the first cycle found if there's no such order.
+pub fn ordered_tables(doc: &DocumentDef) -> Result<Vec<(TableRef, &TableDef)>, TableCycle> {
+    let graph = DependencyGraph::from(doc);
+    let references = |table: &str| -> Vec<&str> {
+        graph
+            .edges
+            .iter()
+            .filter(|v| matches!(v.from, Entity::Column { .. }) && v.from.table() == Some(table))
+            .filter_map(|v| v.to.table())
+            .filter(|v| *v != table)
+            .collect()
+    };
+    let mut pending: Vec<(TableRef, &TableDef)> = vec![];
+    for db in &doc.databases {
+        for schema in &db.schemas {
+            for table in &schema.tables {
+                let table_ref = TableRef {
+                    db: db.name.clone(),
+                    schema: schema.name.clone(),
+                    table: table.name.clone(),
+                };
+                pending.push((table_ref, table));
+            }
+        }
+    }
+    let mut ordered = vec![];
+    let mut done = HashSet::new();
+    while !pending.is_empty() {
+        let (ready, blocked): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|(table_ref, _)| references(&table_ref.qualified_name()).iter().all(|v| done.contains(*v)));
+        if ready.is_empty() {
+            //every blocked table references another blocked table so following them must come back around
+            let mut tables = vec![blocked[0].0.qualified_name()];
+            loop {
+                let last = tables.last().map(|v| v.as_str()).unwrap_or("");
+                let next = references(last)
+                    .into_iter()
+                    .find(|v| !done.contains(*v))
+                    .unwrap_or_default()
+                    .to_owned();
+                if let Some(start) = tables.iter().position(|v| *v == next) {
+                    return Err(TableCycle {
+                        tables: tables.split_off(start),
+                    });
+                }
+                tables.push(next);
+            }
+        }
+        done.extend(ready.iter().map(|v| v.0.qualified_name()));
+        ordered.extend(ready);
+        pending = blocked;
+    }
+    Ok(ordered)
+}
+
+fn flatten<'a>(mappings: &'a [Mapping], out: &mut Vec<&'a Mapping>) {
+    for mapping in mappings {
+        out.push(mapping);
+        flatten(&mapping.children, out);
+    }
+}
+
+///Resolves `table.column`, where table can be qualified, to a column that exists. Tables in the same schema as
+///`near` are preferred when the table name alone is ambiguous.
+fn resolve_column(doc: &DocumentDef, near: Option<&TableRef>, reference: &str) -> Option<Entity> {
+    let (table, column) = reference.trim().rsplit_once('.')?;
+    let table_ref = near
+        .and_then(|v| doc.resolve_table(&format!("{}.{}.{}", v.db, v.schema, table)))
+        .or_else(|| doc.resolve_table(table))?;
+    let table_def = doc.find_table(&table_ref.qualified_name())?;
+    if !table_def.columns.iter().any(|v| v.name == column) {
+        return None;
+    }
+    Some(Entity::Column {
+        table: table_ref.qualified_name(),
+        column: column.to_owned(),
+    })
+}
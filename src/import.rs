@@ -0,0 +1,173 @@
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::sync::Arc;
+
+use rapid_fs::vfs::BoundVfs;
+use rapid_fs::vfs::Vfs;
+#[cfg(feature = "git-imports")]
+use sha2::{Digest, Sha256};
+
+///Reads the files a document imports. Given to the parser with
+///[ParseOptions::import_resolvers](crate::haml_parser::ParseOptions::import_resolvers), each import is read by the
+///first resolver that accepts its name and by a [VfsResolver] over the parse's Vfs when none do.
+///
+///Names are resolved before they get here, a `./` or `../` import in a remote file is relative to that file e.g.
+///`./audit.sql` imported by `https://shared.example.com/pipelines/audit.xml` is
+///`https://shared.example.com/pipelines/audit.sql`. Any other name in a remote file is under its root,
+///`queries/audit.sql` is `https://shared.example.com/queries/audit.sql`, so a remote file never reads the local Vfs.
+pub trait ImportResolver {
+    ///Whether this resolver reads `name` e.g. the https resolver reads names starting with `https://`
+    fn accepts(&self, name: &str) -> bool;
+    ///The contents of the file `name` refers to
+    fn read(&self, name: &str) -> io::Result<String>;
+    ///When true, the element importing a file this resolver reads has to give the file's `sha256` so a file that
+    ///changes after it's been reviewed fails the parse instead of changing the schema
+    fn requires_integrity(&self) -> bool {
+        false
+    }
+}
+
+impl Debug for dyn ImportResolver {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ImportResolver")
+    }
+}
+
+///Reads imports from a Vfs, names are relative to the root the Vfs is bound to
+pub struct VfsResolver<F>
+    where
+        F: Vfs,
+{
+    fs: Arc<BoundVfs<F>>,
+}
+
+impl<F> VfsResolver<F>
+    where
+        F: Vfs,
+{
+    pub fn new(fs: Arc<BoundVfs<F>>) -> Self {
+        VfsResolver { fs }
+    }
+}
+
+impl<F> ImportResolver for VfsResolver<F>
+    where
+        F: Vfs,
+{
+    fn accepts(&self, _name: &str) -> bool {
+        true
+    }
+
+    fn read(&self, name: &str) -> io::Result<String> {
+        self.fs.read_schema_file(name)
+    }
+}
+
+///Reads `https://` imports. Remote files have to be pinned with a `sha256`, see
+///[ImportResolver::requires_integrity]
+#[cfg(feature = "https-imports")]
+pub struct HttpsResolver {
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "https-imports")]
+impl HttpsResolver {
+    pub fn new() -> Self {
+        Self::with_agent(ureq::Agent::new())
+    }
+
+    ///Uses `agent` for requests e.g. to set timeouts or a proxy
+    pub fn with_agent(agent: ureq::Agent) -> Self {
+        HttpsResolver { agent }
+    }
+}
+
+#[cfg(feature = "https-imports")]
+impl Default for HttpsResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "https-imports")]
+impl ImportResolver for HttpsResolver {
+    fn accepts(&self, name: &str) -> bool {
+        name.starts_with("https://")
+    }
+
+    fn read(&self, name: &str) -> io::Result<String> {
+        let response = self
+            .agent
+            .get(name)
+            .call()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        response.into_string()
+    }
+
+    fn requires_integrity(&self) -> bool {
+        true
+    }
+}
+
+///Reads imports from git repositories with the `git` command. Names are written `git+<repo url>#<ref>:<path>` e.g.
+///`git+https://github.com/example/haml-shared.git#v1.2:pipelines/audit.xml`, a `../` import can't leave the ref's
+///tree. Each repo and ref is fetched once into the cache directory and read from there after, so a branch doesn't
+///pick up new commits until the cache is cleared. Remote files have to be pinned with a `sha256`, see
+///[ImportResolver::requires_integrity]
+#[cfg(feature = "git-imports")]
+pub struct GitResolver {
+    cache_dir: std::path::PathBuf,
+}
+
+#[cfg(feature = "git-imports")]
+impl GitResolver {
+    pub fn new(cache_dir: impl Into<std::path::PathBuf>) -> Self {
+        GitResolver { cache_dir: cache_dir.into() }
+    }
+
+    fn git(dir: &std::path::Path, args: &[&str]) -> io::Result<Vec<u8>> {
+        let output = std::process::Command::new("git").arg("-C").arg(dir).args(args).output()?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(io::Error::other(format!("git {} failed. {}", args[0], stderr.trim())))
+        }
+    }
+}
+
+#[cfg(feature = "git-imports")]
+impl ImportResolver for GitResolver {
+    fn accepts(&self, name: &str) -> bool {
+        name.starts_with("git+")
+    }
+
+    fn read(&self, name: &str) -> io::Result<String> {
+        let invalid = || {
+            let message = format!("Expected git+<repo url>#<ref>:<path>, got '{}'", name);
+            io::Error::new(io::ErrorKind::InvalidInput, message)
+        };
+        let (repo, rest) = name["git+".len()..].split_once('#').ok_or_else(invalid)?;
+        let (reference, path) = rest.split_once(':').ok_or_else(invalid)?;
+        if repo.starts_with('-') || reference.starts_with('-') || path.is_empty() {
+            return Err(invalid());
+        }
+        //the ref is part of the directory name so every ref gets its own fetch
+        let dir = self.cache_dir.join(format!("{:x}", Sha256::digest(format!("{}#{}", repo, reference))));
+        if !dir.join(".git").exists() {
+            std::fs::create_dir_all(&dir)?;
+            Self::git(&dir, &["init", "--quiet"])?;
+            if let Err(e) = Self::git(&dir, &["fetch", "--quiet", "--depth", "1", "--", repo, reference]) {
+                //leave nothing behind so the next parse fetches again
+                let _ = std::fs::remove_dir_all(&dir);
+                return Err(e);
+            }
+        }
+        let contents = Self::git(&dir, &["show", &format!("FETCH_HEAD:{}", path)])?;
+        String::from_utf8(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn requires_integrity(&self) -> bool {
+        true
+    }
+}
@@ -1,9 +1,29 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
+use std::time::Duration;
 
 // pub use haml::*;
 pub mod manifested_schema;
 pub mod haml_parser;
+pub mod template;
+pub mod expression;
+pub mod formatter;
+pub mod graph;
+pub mod merge;
+pub mod lint;
+pub mod sql;
+pub mod functions;
+pub mod diagnostic;
+#[cfg(feature = "serde")]
+pub mod sarif;
+pub mod symbols;
+pub mod refactor;
+pub mod cst;
+pub mod import;
+mod fingerprint;
+
+pub use formatter::format;
 
 #[derive(Debug, Default, Clone)]
 pub struct Location {
@@ -13,7 +33,206 @@ pub struct Location {
     pub child_index: u64,
 }
 
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file_name, self.line, self.column)
+    }
+}
+
+///The version of the HAML markup a document was written for, declared with `<document haml-version="1.0">`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct HamlVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl HamlVersion {
+    ///The newest version of the markup this crate understands.
+    ///Documents with the same major version can be parsed, a newer minor version may use features this crate ignores.
+    pub const CURRENT: HamlVersion = HamlVersion { major: 1, minor: 0 };
+
+    pub fn is_compatible(&self) -> bool {
+        self.major == HamlVersion::CURRENT.major
+    }
+}
+
+impl FromStr for HamlVersion {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut parts = input.trim().split('.');
+        let major = parts.next().unwrap_or("");
+        let minor = parts.next().unwrap_or("0");
+        match (major.parse(), minor.parse(), parts.next()) {
+            (Ok(major), Ok(minor), None) => Ok(HamlVersion { major, minor }),
+            _ => Err(format!("Invalid HAML version '{}', expected major.minor e.g. '1.0'", input)),
+        }
+    }
+}
+
+impl Display for HamlVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+///Where a table is declared, `db` is the label of the db element the table's schema is in
 #[derive(Debug, PartialEq, Clone)]
+pub struct TableRef {
+    pub db: String,
+    pub schema: String,
+    pub table: String,
+}
+
+impl TableRef {
+    ///The fully qualified name of the table, `db.schema.table`
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}.{}", self.db, self.schema, self.table)
+    }
+
+    ///True if `name` refers to this table, where `name` is `table`, `schema.table` or `db.schema.table`
+    pub fn matches(&self, name: &str) -> bool {
+        match name.trim().split('.').collect::<Vec<_>>().as_slice() {
+            [table] => *table == self.table,
+            [schema, table] => *schema == self.schema && *table == self.table,
+            [db, schema, table] => *db == self.db && *schema == self.schema && *table == self.table,
+            _ => false,
+        }
+    }
+}
+
+///The status of a response, written as a number between 100 and 599 or the upper case name of a standard status
+///e.g. `status="201"` or `status="CREATED"`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct HttpStatus(pub u16);
+
+impl HttpStatus {
+    ///The status' reason phrase if it is a standard status e.g. `Created` for 201
+    pub fn reason(&self) -> Option<&'static str> {
+        http::StatusCode::from_u16(self.0).ok().and_then(|v| v.canonical_reason())
+    }
+
+    ///True for 2xx statuses
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    ///The name a standard status can be written as, its reason phrase in upper snake case e.g. `NOT_FOUND`
+    fn name(code: u16) -> Option<String> {
+        let reason = http::StatusCode::from_u16(code).ok()?.canonical_reason()?;
+        let name: String = reason
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-')
+            .map(|c| if c.is_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        Some(name)
+    }
+}
+
+impl FromStr for HttpStatus {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let code = match input.parse::<u16>() {
+            Ok(code) => Some(code),
+            Err(_) => {
+                let name = input.to_uppercase().replace(['-', ' '], "_");
+                (100..600).find(|v| HttpStatus::name(*v).as_deref() == Some(name.as_str()))
+            }
+        };
+        match code {
+            Some(code @ 100..=599) => Ok(HttpStatus(code)),
+            _ => Err(format!(
+                "The status must be a number between 100 and 599 or the name of a standard status like CREATED - got '{}'.",
+                input
+            )),
+        }
+    }
+}
+
+impl Display for HttpStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+///A media type such as `application/json` or `text/plain; charset=utf-8`, used by an endpoint's accepts and
+///produces attributes. The type, subtype and parameter names are lower case.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MediaType {
+    pub typ: String,
+    pub subtype: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl MediaType {
+    pub fn json() -> MediaType {
+        MediaType {
+            typ: "application".to_owned(),
+            subtype: "json".to_owned(),
+            params: vec![],
+        }
+    }
+
+    ///True if this type is covered by `pattern`, which can use `*` for the type or subtype e.g. `image/*`.
+    ///Parameters are ignored
+    pub fn matches(&self, pattern: &MediaType) -> bool {
+        (pattern.typ == "*" || pattern.typ == self.typ) && (pattern.subtype == "*" || pattern.subtype == self.subtype)
+    }
+}
+
+impl FromStr for MediaType {
+    type Err = String;
+
+    ///Parses `type/subtype *(; name=value)` where names, types and unquoted values are RFC 7230 tokens
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| format!("Invalid media type '{}'. {}", input, reason);
+        let is_token = |v: &str| !v.is_empty() && v.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+        let mut parts = input.split(';');
+        let (typ, subtype) = parts
+            .next()
+            .unwrap_or("")
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| invalid("Expected type/subtype e.g. application/json"))?;
+        if !is_token(typ) || !is_token(subtype) {
+            return Err(invalid("Expected type/subtype e.g. application/json"));
+        }
+        if typ == "*" && subtype != "*" {
+            return Err(invalid("Only */* can have a wildcard type"));
+        }
+        let mut params = vec![];
+        for param in parts {
+            let (name, value) = param
+                .trim()
+                .split_once('=')
+                .ok_or_else(|| invalid("Parameters must be name=value"))?;
+            let quoted = value.len() >= 2 && value.starts_with('"') && value.ends_with('"');
+            if !is_token(name) || !(quoted || is_token(value)) {
+                return Err(invalid(&format!("'{}' is not a valid parameter", param.trim())));
+            }
+            params.push((name.to_lowercase(), value.to_owned()));
+        }
+        Ok(MediaType {
+            typ: typ.to_lowercase(),
+            subtype: subtype.to_lowercase(),
+            params,
+        })
+    }
+}
+
+impl Display for MediaType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.typ, self.subtype)?;
+        for (name, value) in &self.params {
+            write!(f, "; {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum CoreApi {
     Register,
     LoginByEmail,
@@ -29,6 +248,72 @@ pub enum CoreApi {
     VerifyAccount,
 }
 
+impl CoreApi {
+    ///Every core API, in the order they're offered to editors
+    pub const ALL: &'static [CoreApi] = &[
+        CoreApi::Register,
+        CoreApi::LoginByEmail,
+        CoreApi::LoginByUsername,
+        CoreApi::OAuth,
+        CoreApi::PasswordResetTrigger,
+        CoreApi::PasswordReset,
+        CoreApi::VerifyAccount,
+        CoreApi::MagicLink,
+        CoreApi::TwoFactorAuthEmail,
+        CoreApi::TwoFactorAuthSms,
+        CoreApi::TwoFactorStep2,
+        CoreApi::TwoFactorTotp,
+    ];
+    ///The name of each of [CoreApi::ALL]
+    pub const NAMES: [&'static str; Self::ALL.len()] = {
+        let mut names = [""; Self::ALL.len()];
+        let mut i = 0;
+        while i < names.len() {
+            names[i] = Self::ALL[i].name();
+            i += 1;
+        }
+        names
+    };
+
+    ///The name the core API is enabled by, `<core-api name="login-by-email"/>`
+    pub const fn name(&self) -> &'static str {
+        match self {
+            CoreApi::Register => "register",
+            CoreApi::LoginByEmail => "login-by-email",
+            CoreApi::LoginByUsername => "login-by-username",
+            CoreApi::OAuth => "oauth",
+            CoreApi::PasswordResetTrigger => "password-reset-trigger",
+            CoreApi::PasswordReset => "password-reset",
+            CoreApi::VerifyAccount => "verify-account",
+            CoreApi::MagicLink => "magic-link",
+            CoreApi::TwoFactorAuthEmail => "2fa-email",
+            CoreApi::TwoFactorAuthSms => "2fa-sms",
+            CoreApi::TwoFactorStep2 => "2fa-step2",
+            CoreApi::TwoFactorTotp => "2fa-totp",
+        }
+    }
+}
+
+///The names core APIs are enabled by, `<core-api name="login-by-email"/>`
+impl FromStr for CoreApi {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let name = input.to_lowercase();
+        Self::ALL
+            .iter()
+            .find(|v| v.name() == name)
+            .cloned()
+            .ok_or_else(|| format!("No core api supported with the name '{}'.", input))
+    }
+}
+
+impl Display for CoreApi {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum DatabaseType {
     MekaDb,
@@ -37,18 +322,138 @@ pub enum DatabaseType {
     MariaDB,
     Oracle,
     MsSql,
+    ///File based, configured with the `file` attribute instead of host/port
+    Sqlite,
+    CockroachDB,
 }
 
 impl DatabaseType {
+    ///Every database type, in the order they're offered to editors
+    pub const ALL: &'static [DatabaseType] = &[
+        DatabaseType::MekaDb,
+        DatabaseType::Postgres,
+        DatabaseType::MySQL,
+        DatabaseType::MariaDB,
+        DatabaseType::Oracle,
+        DatabaseType::MsSql,
+        DatabaseType::Sqlite,
+        DatabaseType::CockroachDB,
+    ];
+    ///The name of each of [DatabaseType::ALL]
+    pub const NAMES: [&'static str; Self::ALL.len()] = {
+        let mut names = [""; Self::ALL.len()];
+        let mut i = 0;
+        while i < names.len() {
+            names[i] = Self::ALL[i].name();
+            i += 1;
+        }
+        names
+    };
+
+    ///The name the type is given by, `<db type="postgres"/>`
+    pub const fn name(&self) -> &'static str {
+        match self {
+            DatabaseType::MekaDb => "mekadb",
+            DatabaseType::Postgres => "postgres",
+            DatabaseType::MySQL => "mysql",
+            DatabaseType::MariaDB => "mariadb",
+            DatabaseType::Oracle => "oracle",
+            DatabaseType::MsSql => "mssql",
+            DatabaseType::Sqlite => "sqlite",
+            DatabaseType::CockroachDB => "cockroachdb",
+        }
+    }
+
     pub fn from(v: &String) -> Option<DatabaseType> {
         match v.to_lowercase().as_str() {
-            "mekadb" => Some(DatabaseType::MekaDb),
-            "postgres" => Some(DatabaseType::Postgres),
-            "mysql" => Some(DatabaseType::MySQL),
-            "mariadb" => Some(DatabaseType::MariaDB),
-            "oracle" => Some(DatabaseType::Oracle),
-            "mssql" => Some(DatabaseType::MsSql),
-            _ => None,
+            "cockroach" => Some(DatabaseType::CockroachDB),
+            v => Self::ALL.iter().find(|t| t.name() == v).cloned(),
+        }
+    }
+}
+
+const SQL_RESERVED_WORDS: &[&str] = &[
+    "all", "and", "any", "as", "asc", "between", "by", "case", "check", "column", "constraint",
+    "create", "cross", "current_date", "current_time", "current_timestamp", "current_user",
+    "default", "delete", "desc", "distinct", "drop", "else", "end", "exists", "false", "fetch",
+    "for", "foreign", "from", "grant", "group", "having", "in", "inner", "insert", "intersect",
+    "into", "is", "join", "left", "like", "not", "null", "on", "or", "order", "outer", "primary",
+    "references", "right", "select", "set", "table", "then", "to", "true", "union", "unique",
+    "update", "user", "using", "values", "when", "where", "with",
+];
+
+const POSTGRES_RESERVED_WORDS: &[&str] = &[
+    "analyse", "analyze", "array", "asymmetric", "both", "cast", "collate", "current_catalog",
+    "current_role", "deferrable", "do", "except", "initially", "lateral", "leading", "limit",
+    "localtime", "localtimestamp", "offset", "only", "placing", "returning", "session_user",
+    "some", "symmetric", "trailing", "variadic", "window",
+];
+
+const SQLITE_RESERVED_WORDS: &[&str] = &[
+    "abort", "action", "add", "after", "alter", "analyze", "attach", "autoincrement", "before",
+    "begin", "cascade", "cast", "collate", "commit", "conflict", "database", "deferrable",
+    "deferred", "detach", "each", "escape", "except", "exclusive", "explain", "fail", "glob",
+    "if", "ignore", "immediate", "index", "indexed", "initially", "instead", "isnull", "key",
+    "limit", "match", "natural", "no", "notnull", "of", "offset", "plan", "pragma", "query",
+    "raise", "recursive", "regexp", "reindex", "release", "rename", "replace", "restrict",
+    "rollback", "row", "savepoint", "temp", "temporary", "transaction", "trigger", "vacuum",
+    "view", "virtual", "without",
+];
+
+const MYSQL_RESERVED_WORDS: &[&str] = &[
+    "add", "alter", "call", "change", "condition", "database", "databases", "describe", "div",
+    "explain", "force", "fulltext", "groups", "index", "interval", "key", "keys", "kill", "limit",
+    "lock", "match", "mod", "natural", "range", "rank", "read", "rename", "replace", "row",
+    "rows", "schema", "show", "sql", "status", "trigger", "usage", "window", "write",
+];
+
+const ORACLE_RESERVED_WORDS: &[&str] = &[
+    "access", "add", "alter", "audit", "cluster", "comment", "compress", "connect", "current",
+    "date", "exclusive", "file", "identified", "immediate", "increment", "index", "initial",
+    "level", "lock", "long", "minus", "mode", "modify", "nowait", "number", "of", "offline",
+    "online", "option", "prior", "public", "raw", "rename", "resource", "revoke", "row",
+    "rowid", "rownum", "rows", "session", "share", "size", "start", "synonym", "sysdate",
+    "trigger", "uid", "validate", "view", "whenever",
+];
+
+const MSSQL_RESERVED_WORDS: &[&str] = &[
+    "add", "alter", "authorization", "backup", "begin", "break", "browse", "bulk", "cascade",
+    "checkpoint", "close", "clustered", "coalesce", "collate", "commit", "compute", "contains",
+    "continue", "convert", "current", "cursor", "database", "dbcc", "deallocate", "declare",
+    "deny", "distributed", "double", "errlvl", "escape", "except", "exec", "execute", "exit",
+    "external", "file", "fillfactor", "freetext", "full", "function", "goto", "holdlock",
+    "identity", "if", "index", "key", "kill", "lineno", "merge", "national", "nocheck",
+    "nonclustered", "nullif", "of", "off", "offsets", "open", "option", "over", "percent",
+    "pivot", "plan", "print", "proc", "procedure", "public", "raiserror", "read", "replication",
+    "restore", "restrict", "return", "revert", "revoke", "rollback", "rowcount", "rule", "save",
+    "schema", "session_user", "setuser", "shutdown", "some", "statistics", "system_user",
+    "tablesample", "top", "tran", "transaction", "trigger", "truncate", "unpivot", "use",
+    "varying", "view", "waitfor", "while",
+];
+
+impl DatabaseType {
+    ///True if `name` is a reserved word for this database and so cannot be used unquoted as an identifier.
+    pub fn is_reserved_word(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        let name = name.as_str();
+        SQL_RESERVED_WORDS.contains(&name)
+            || match self {
+            DatabaseType::MekaDb => false,
+            //CockroachDB uses the Postgres dialect
+            DatabaseType::Postgres | DatabaseType::CockroachDB => POSTGRES_RESERVED_WORDS.contains(&name),
+            DatabaseType::Sqlite => SQLITE_RESERVED_WORDS.contains(&name),
+            DatabaseType::MySQL | DatabaseType::MariaDB => MYSQL_RESERVED_WORDS.contains(&name),
+            DatabaseType::Oracle => ORACLE_RESERVED_WORDS.contains(&name),
+            DatabaseType::MsSql => MSSQL_RESERVED_WORDS.contains(&name),
+        }
+    }
+
+    ///Quotes `name` the way this database expects delimited identifiers to be written.
+    pub fn quote_identifier(&self, name: &str) -> String {
+        match self {
+            DatabaseType::MySQL | DatabaseType::MariaDB => format!("`{}`", name),
+            DatabaseType::MsSql => format!("[{}]", name),
+            _ => format!("\"{}\"", name),
         }
     }
 }
@@ -62,6 +467,8 @@ impl Display for DatabaseType {
             DatabaseType::MariaDB => f.write_str("MariaDB"),
             DatabaseType::Oracle => f.write_str("Oracle"),
             DatabaseType::MsSql => f.write_str("MsSql"),
+            DatabaseType::Sqlite => f.write_str("Sqlite"),
+            DatabaseType::CockroachDB => f.write_str("CockroachDB"),
         }
     }
 }
@@ -81,11 +488,13 @@ pub enum TableConstraintType {
     Unique,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ImplicitDockerStepPosition {
     First,
     Each,
     Last,
+    ///The step with the given name e.g. `before="step:validate"`
+    Step(String),
 }
 
 impl FromStr for ImplicitDockerStepPosition {
@@ -95,8 +504,52 @@ impl FromStr for ImplicitDockerStepPosition {
         match input {
             "first" => Ok(ImplicitDockerStepPosition::First),
             "each" => Ok(ImplicitDockerStepPosition::Each),
-            "last" => Ok(ImplicitDockerStepPosition::Each),
-            _ => Err(format!("Invalid position '{}'", input)),
+            "last" => Ok(ImplicitDockerStepPosition::Last),
+            _ => match input.strip_prefix("step:") {
+                Some(name) if !name.is_empty() => Ok(ImplicitDockerStepPosition::Step(name.to_owned())),
+                _ => Err(format!("Invalid position '{}'", input)),
+            },
+        }
+    }
+}
+
+///Where an implicit step runs relative to the steps of a pipeline.
+///Both can be set e.g. a logger that runs before and after each step.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StepPlacement {
+    pub before: Option<ImplicitDockerStepPosition>,
+    pub after: Option<ImplicitDockerStepPosition>,
+}
+
+impl StepPlacement {
+    ///The indices in `steps` that the implicit step should be inserted at, in ascending order.
+    ///An index equal to `steps.len()` means after the last step and an index appears once for each copy of the step
+    ///to insert there. Positions that refer to a step that doesn't exist are ignored.
+    pub fn insertion_indices(&self, steps: &[&str]) -> Vec<usize> {
+        let mut indices = vec![];
+        if let Some(position) = &self.before {
+            indices.extend(Self::indices(position, steps, 0));
+        }
+        if let Some(position) = &self.after {
+            indices.extend(Self::indices(position, steps, 1));
+        }
+        indices.sort();
+        indices
+    }
+
+    fn indices(position: &ImplicitDockerStepPosition, steps: &[&str], offset: usize) -> Vec<usize> {
+        if steps.is_empty() {
+            return vec![];
+        }
+        match position {
+            ImplicitDockerStepPosition::First => vec![offset],
+            ImplicitDockerStepPosition::Each => (0..steps.len()).map(|i| i + offset).collect(),
+            ImplicitDockerStepPosition::Last => vec![steps.len() - 1 + offset],
+            ImplicitDockerStepPosition::Step(name) => steps
+                .iter()
+                .position(|v| v == name)
+                .map(|i| vec![i + offset])
+                .unwrap_or_else(|| vec![]),
         }
     }
 }
@@ -109,6 +562,761 @@ pub struct DockerConnectionInfo {
     pub password: Option<String>,
     pub image: String,
     pub tag: Option<String>,
+    ///Pins the image to an exact build, `image@sha256:<digest>`. Includes the algorithm prefix
+    pub digest: Option<String>,
+    pub healthcheck: Option<DockerHealthcheck>,
+    ///How to authenticate with the registry, preferred over `username` and `password`
+    pub credentials: Option<CredentialRef>,
+    ///Build args passed to the image build, `<arg name="RUST_VERSION" value="1.79"/>`
+    pub build_args: Vec<BuildArg>,
+    ///The directory the image is built from
+    pub context: Option<String>,
+    ///Whether layers from previous builds can be reused, the builder decides if not set
+    pub cache: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BuildArg {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub name: String,
+    pub value: String,
+}
+
+///Where the credentials for a docker registry come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CredentialRef {
+    ///Read from an environment variable, `credentials="env:REGISTRY_CREDS"`
+    Env(String),
+    ///A secret managed by the platform, `credentials-secret="registry-creds"`
+    Secret(String),
+    ///Deprecated, written into the image reference as `user:pass@image:tag`
+    Inline { username: String, password: String },
+}
+
+impl FromStr for CredentialRef {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.strip_prefix("env:") {
+            Some(name) if !name.is_empty() => Ok(CredentialRef::Env(name.to_owned())),
+            _ => Err(format!("Invalid credentials reference '{}'. Expected env:<VARIABLE NAME>", input)),
+        }
+    }
+}
+
+///How to tell when a container is ready to receive work.
+///Either runs `command` in the container or makes a HTTP GET request to `path` on `port`,
+///e.g. `<healthcheck command="curl -f localhost" interval="10s" retries="3"/>`
+#[derive(Debug, Clone, Default)]
+pub struct DockerHealthcheck {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub command: Option<String>,
+    pub path: Option<String>,
+    pub port: Option<u16>,
+    pub interval: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+}
+
+///Connection pool settings for a database,
+///e.g. `<db max-connections="20" min-connections="2" connect-timeout="5s" idle-timeout="10m"/>`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoolConfig {
+    pub max_connections: Option<u32>,
+    pub min_connections: Option<u32>,
+    pub connect_timeout: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+}
+
+///How strictly TLS is used when connecting to a database
+#[derive(Debug, Clone, PartialEq)]
+pub enum SslMode {
+    Disable,
+    ///Use TLS without verifying the server's certificate
+    Require,
+    ///Use TLS, verifying the server's certificate and host name
+    VerifyFull,
+}
+
+impl FromStr for SslMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "disable" => Ok(SslMode::Disable),
+            "require" => Ok(SslMode::Require),
+            "verify-full" => Ok(SslMode::VerifyFull),
+            _ => Err(format!("Invalid ssl-mode '{}'. Expected one of disable, require or verify-full", input)),
+        }
+    }
+}
+
+///A hash function a mapping value can be passed through, `transform="hash(bcrypt)"`
+#[derive(Debug, Clone, PartialEq)]
+pub enum HashAlgorithm {
+    Bcrypt,
+    Argon2,
+    Sha256,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_lowercase().as_str() {
+            "bcrypt" => Ok(HashAlgorithm::Bcrypt),
+            "argon2" => Ok(HashAlgorithm::Argon2),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(format!("Invalid hash algorithm '{}'. Expected one of bcrypt, argon2 or sha256", input)),
+        }
+    }
+}
+
+///A transformation applied to a mapping's value, in the order they're written
+///e.g. `transform="trim|lowercase|custom:normalise_email"`
+#[derive(Debug, Clone, PartialEq)]
+pub enum MappingTransform {
+    Lowercase,
+    Uppercase,
+    Trim,
+    Hash(HashAlgorithm),
+    ///A function provided by the runtime, `custom:fn_name`
+    Custom(String),
+}
+
+impl FromStr for MappingTransform {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        if let Some(name) = input.strip_prefix("custom:") {
+            return if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                Ok(MappingTransform::Custom(name.to_owned()))
+            } else {
+                Err(format!("Invalid custom transform '{}'. The function name must be alphanumeric or _", name))
+            };
+        }
+        if let Some(algorithm) = input.strip_prefix("hash(").and_then(|v| v.strip_suffix(')')) {
+            return Ok(MappingTransform::Hash(algorithm.parse()?));
+        }
+        match input.to_lowercase().as_str() {
+            "lowercase" => Ok(MappingTransform::Lowercase),
+            "uppercase" => Ok(MappingTransform::Uppercase),
+            "trim" => Ok(MappingTransform::Trim),
+            _ => Err(format!(
+                "Invalid transform '{}'. Expected lowercase, uppercase, trim, hash(algorithm) or custom:fn_name",
+                input
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncryptionAlgorithm {
+    AesGcm,
+}
+
+impl FromStr for EncryptionAlgorithm {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "aes-gcm" => Ok(EncryptionAlgorithm::AesGcm),
+            _ => Err(format!("Invalid encryption algorithm '{}'. Expected aes-gcm", input)),
+        }
+    }
+}
+
+///Field level encryption at rest, `<column name="ssn" type="TEXT" encrypt="aes-gcm" key-ref="env:DATA_KEY"/>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnEncryption {
+    pub algorithm: EncryptionAlgorithm,
+    ///Where the data key comes from
+    pub key_ref: CredentialRef,
+}
+
+///How a sensitive column's value is hidden when it is read or logged
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum MaskPolicy {
+    ///Show only the last 4 characters e.g. `************4242`
+    Last4,
+    ///Replace the value with a hash of it so equal values can still be compared
+    Hash,
+    ///Replace the value entirely
+    #[default]
+    Redact,
+}
+
+impl MaskPolicy {
+    ///The column pipeline read function that applies this mask
+    pub fn read_function(&self) -> &'static str {
+        match self {
+            MaskPolicy::Last4 => "mask_last4",
+            MaskPolicy::Hash => "mask_hash",
+            MaskPolicy::Redact => "redact",
+        }
+    }
+}
+
+impl FromStr for MaskPolicy {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "last4" => Ok(MaskPolicy::Last4),
+            "hash" => Ok(MaskPolicy::Hash),
+            "redact" => Ok(MaskPolicy::Redact),
+            _ => Err(format!("Invalid mask '{}'. Expected one of last4, hash or redact", input)),
+        }
+    }
+}
+
+///Input rules for a column, e.g. `<column name="age" type="int" min="0" max="150"/>`.
+///min and max apply to numeric columns, the others to text columns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnValidation {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    ///A regular expression the whole value must match
+    pub pattern: Option<String>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+}
+
+///How a runtime treats request and response fields that don't match their declaration
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ValidationMode {
+    ///Reject anything that doesn't match the declaration
+    Strict,
+    ///Coerce values to the declared types and drop unexpected fields
+    #[default]
+    Lenient,
+    Off,
+}
+
+impl FromStr for ValidationMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "strict" => Ok(ValidationMode::Strict),
+            "lenient" => Ok(ValidationMode::Lenient),
+            "off" => Ok(ValidationMode::Off),
+            _ => Err(format!("Invalid validation '{}'. Expected one of strict, lenient or off", input)),
+        }
+    }
+}
+
+///TLS settings for a database connection,
+///e.g. `<db ssl-mode="verify-full" ca-cert="certs/ca.pem" client-cert="certs/app.pem" client-key="certs/app.key"/>`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DbTlsConfig {
+    pub ssl_mode: Option<SslMode>,
+    pub ca_cert: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+}
+
+///Settings for the 2fa-totp core API, read from the options of its core-api element
+///e.g. `<core-api name="2fa-totp" issuer="Acme" digits="6" period="30s" drift="1"/>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TotpConfig {
+    ///The name shown in authenticator apps, the runtime uses the app's name if not set
+    pub issuer: Option<String>,
+    pub digits: u8,
+    pub period: Duration,
+    ///How many periods before or after the current one a code is still accepted for
+    pub drift: u32,
+}
+
+impl Default for TotpConfig {
+    fn default() -> Self {
+        TotpConfig {
+            issuer: None,
+            digits: 6,
+            period: Duration::from_secs(30),
+            drift: 1,
+        }
+    }
+}
+
+impl TotpConfig {
+    ///Reads the config from core-api options, returns the name of the first invalid option if any
+    pub fn from_options(options: &HashMap<String, String>) -> Result<TotpConfig, String> {
+        let mut config = TotpConfig::default();
+        if let Some(issuer) = options.get("issuer") {
+            config.issuer = Some(issuer.clone());
+        }
+        if let Some(digits) = options.get("digits") {
+            config.digits = digits
+                .parse()
+                .ok()
+                .filter(|v| (6..=8).contains(v))
+                .ok_or_else(|| "digits".to_owned())?;
+        }
+        if let Some(period) = options.get("period") {
+            config.period = parse_duration(period)
+                .ok()
+                .filter(|v| !v.is_zero())
+                .ok_or_else(|| "period".to_owned())?;
+        }
+        if let Some(drift) = options.get("drift") {
+            config.drift = drift.parse().map_err(|_| "drift".to_owned())?;
+        }
+        Ok(config)
+    }
+}
+
+///Locks an account after too many failed logins,
+///`<lockout max-attempts="5" window="10m" cooldown="30m"/>` allows 5 failures in 10 minutes then locks for 30 minutes
+#[derive(Debug, Clone, Default)]
+pub struct LockoutPolicy {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub max_attempts: u32,
+    ///How far back failed attempts are counted
+    pub window: Duration,
+    ///How long the account stays locked
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum StorageProvider {
+    S3,
+    Gcs,
+    ///Files are stored on the server's disk under `path`
+    #[default]
+    Local,
+}
+
+impl FromStr for StorageProvider {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "s3" => Ok(StorageProvider::S3),
+            "gcs" => Ok(StorageProvider::Gcs),
+            "local" => Ok(StorageProvider::Local),
+            _ => Err(format!("Invalid storage provider '{}'. Expected one of s3, gcs or local", input)),
+        }
+    }
+}
+
+///Where files uploaded to the well-known file table are kept,
+///e.g. `<storage provider="s3" bucket="uploads" region="eu-west-2" credentials-env="S3_CREDS" max-upload-size="10mb"/>`
+#[derive(Debug, Clone, Default)]
+pub struct StorageConfig {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub provider: StorageProvider,
+    pub bucket: Option<String>,
+    pub region: Option<String>,
+    ///The directory files are written to by the local provider
+    pub path: Option<String>,
+    pub credentials: Option<CredentialRef>,
+    ///The largest upload accepted in bytes, not limited if None
+    pub max_upload_size: Option<u64>,
+    ///The content types uploads are allowed to have e.g. `image/*`, any type is allowed if empty
+    pub content_types: Vec<String>,
+}
+
+///How the application is instrumented, e.g.
+///```xml
+///<observability>
+///    <tracing endpoint-env="OTLP_ENDPOINT" sample-rate="0.1"/>
+///    <metrics prefix="shop"/>
+///    <logging level="info" format="json"/>
+///</observability>
+///```
+///Anything not set is left to the host running the application
+#[derive(Debug, Clone, Default)]
+pub struct ObservabilityConfig {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub tracing: Option<TracingConfig>,
+    pub metrics: Option<MetricsConfig>,
+    pub logging: Option<LoggingConfig>,
+}
+
+///Where traces are exported to over OTLP
+#[derive(Debug, Clone, Default)]
+pub struct TracingConfig {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    ///The env var holding the OTLP collector's endpoint
+    pub endpoint_env: String,
+    ///The fraction of requests traced, between 0 and 1
+    pub sample_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub enabled: bool,
+    ///Put in front of the name of every metric the application reports
+    pub prefix: Option<String>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            start_pos: Location::default(),
+            end_pos: Location::default(),
+            enabled: true,
+            prefix: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoggingConfig {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub level: Option<LogLevel>,
+    pub format: Option<LogFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "trace" => Ok(LogLevel::Trace),
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(format!("Invalid log level '{}'. Expected one of trace, debug, info, warn or error", input)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format '{}'. Expected text or json", input)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum MessageProviderKind {
+    #[default]
+    Sms,
+    Email,
+}
+
+///Sends the SMS or emails needed by core APIs like 2fa-sms and magic-link,
+///e.g. `<email-provider type="sendgrid" credentials="env:SENDGRID_KEY" from="no-reply@example.com"/>`
+#[derive(Debug, Clone, Default)]
+pub struct MessageProvider {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub kind: MessageProviderKind,
+    ///The service used to send messages e.g. twilio, smtp or sendgrid
+    pub provider: String,
+    pub credentials: Option<CredentialRef>,
+    ///The address or number messages are sent from
+    pub from: String,
+    ///A directory of message templates, the runtime's defaults are used if not set
+    pub template_dir: Option<String>,
+}
+
+///A read replica of a database, `<replica host="replica-1.internal" port="5432"/>`
+#[derive(Debug, Clone, Default)]
+pub struct DbReplica {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub host: String,
+    ///Defaults to the port of the primary if not set
+    pub port: Option<u16>,
+}
+
+///Where `sql` steps that only read data are sent when a database has replicas
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ReadPreference {
+    #[default]
+    Primary,
+    ///Read from a replica, falling back to the primary if none are available
+    ReplicaPreferred,
+    Replica,
+}
+
+impl FromStr for ReadPreference {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "primary" => Ok(ReadPreference::Primary),
+            "replica-preferred" => Ok(ReadPreference::ReplicaPreferred),
+            "replica" => Ok(ReadPreference::Replica),
+            _ => Err(format!(
+                "Invalid read-preference '{}'. Expected one of primary, replica-preferred or replica",
+                input
+            )),
+        }
+    }
+}
+
+///Parses `true`, `false`, `yes`, `no`, `1` or `0`, ignoring case
+pub fn parse_bool(input: &str) -> Result<bool, String> {
+    match input.trim().to_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("Invalid boolean '{}'. Expected true, false, yes, no, 1 or 0", input)),
+    }
+}
+
+///Parses a size such as `512`, `64kb`, `10MB` or `1gb` into bytes. A number without a unit is in bytes.
+///Units are powers of 1024 and can also be written `kib`, `mib` etc.
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let invalid = || format!("Invalid size '{}'. Expected a number followed by b, kb, mb, gb or tb", input);
+    let split = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    let (amount, unit) = input.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        "t" | "tb" | "tib" => 1024 * 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    amount
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("The size '{}' is too large", input))
+}
+
+///Parses a duration such as `500ms`, `30s`, `5m`, `2h` or `1d`. A number without a unit is in seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let invalid = || format!("Invalid duration '{}'. Expected a number followed by ms, s, m, h or d", input);
+    let split = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    let (amount, unit) = input.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = |multiplier: u64| {
+        amount
+            .checked_mul(multiplier)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("The duration '{}' is too long", input))
+    };
+    match unit.trim().to_lowercase().as_str() {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "" | "s" => seconds(1),
+        "m" => seconds(60),
+        "h" => seconds(60 * 60),
+        "d" => seconds(24 * 60 * 60),
+        _ => Err(invalid()),
+    }
+}
+
+///When the image for a step is pulled from its registry
+#[derive(Debug, Clone, PartialEq)]
+pub enum PullPolicy {
+    Always,
+    IfNotPresent,
+    Never,
+}
+
+impl FromStr for PullPolicy {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "always" => Ok(PullPolicy::Always),
+            "if-not-present" => Ok(PullPolicy::IfNotPresent),
+            "never" => Ok(PullPolicy::Never),
+            _ => Err(format!("Invalid pull policy '{}'", input)),
+        }
+    }
+}
+
+///The language of a script step, `<script lang="python">`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ScriptType {
+    #[default]
+    JavaScript,
+    TypeScript,
+    Python,
+    Lua,
+}
+
+impl ScriptType {
+    ///The language a script file is written in going by its extension e.g. `transform.py`
+    pub fn from_file_name(file_name: &str) -> Option<ScriptType> {
+        match file_name.rsplit_once('.')?.1.to_lowercase().as_str() {
+            "js" | "mjs" => Some(ScriptType::JavaScript),
+            "ts" => Some(ScriptType::TypeScript),
+            "py" => Some(ScriptType::Python),
+            "lua" => Some(ScriptType::Lua),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ScriptType {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "javascript" | "js" => Ok(ScriptType::JavaScript),
+            "typescript" | "ts" => Ok(ScriptType::TypeScript),
+            "python" => Ok(ScriptType::Python),
+            "lua" => Ok(ScriptType::Lua),
+            _ => Err(format!(
+                "Invalid script language '{}'. Supported languages are javascript, typescript, python OR lua",
+                input
+            )),
+        }
+    }
+}
+
+impl Display for ScriptType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptType::JavaScript => f.write_str("javascript"),
+            ScriptType::TypeScript => f.write_str("typescript"),
+            ScriptType::Python => f.write_str("python"),
+            ScriptType::Lua => f.write_str("lua"),
+        }
+    }
+}
+
+///The sandbox budget of a script step, `<script memory-limit="64m" cpu-ms="500" allow-net="false">`. Limits that
+///aren't set are left to the host running the script
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScriptLimits {
+    ///In bytes
+    pub memory: Option<u64>,
+    ///CPU time the script can use per run, in milliseconds
+    pub cpu_ms: Option<u64>,
+    ///Whether the script can make network requests
+    pub allow_net: Option<bool>,
+}
+
+///What a runtime can log of the payloads an endpoint or step handles, `log="metadata" redact-fields="password,token"`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogPolicy {
+    ///Left to the runtime when not set
+    pub mode: Option<LogMode>,
+    ///Fields whose values are redacted wherever they appear in a logged payload
+    pub redact_fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogMode {
+    ///Nothing about the request is logged
+    None,
+    ///Things like the route, status and timing but not the payloads
+    Metadata,
+    ///Payloads are logged, less any [redacted fields](LogPolicy::redact_fields)
+    Full,
+}
+
+impl FromStr for LogMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_lowercase().as_str() {
+            "none" => Ok(LogMode::None),
+            "metadata" => Ok(LogMode::Metadata),
+            "full" => Ok(LogMode::Full),
+            _ => Err(format!("Invalid log mode '{}'. Expected one of none, metadata or full", input)),
+        }
+    }
+}
+
+///Limits on the requests an endpoint accepts, `<endpoint max-body-size="5MB" max-files="3">`. Endpoints fall back
+///to the limits set on global-options, limits set in neither are left to the host
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RequestLimits {
+    ///In bytes
+    pub max_body_size: Option<u64>,
+    ///How many files a multipart request can upload
+    pub max_files: Option<u32>,
+}
+
+impl RequestLimits {
+    ///These limits with any that aren't set taken from `defaults`
+    pub fn or(&self, defaults: &RequestLimits) -> RequestLimits {
+        RequestLimits {
+            max_body_size: self.max_body_size.or(defaults.max_body_size),
+            max_files: self.max_files.or(defaults.max_files),
+        }
+    }
+}
+
+///A container port exposed by a step, `<port container="8080" host="80" protocol="tcp"/>`
+#[derive(Debug, Clone, Default)]
+pub struct DockerPort {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub container: u16,
+    ///The port on the host to bind to, a random port is used if not set
+    pub host: Option<u16>,
+    pub protocol: String,
+}
+
+///A volume mounted into a step's container, `<volume source="data" target="/data" read-only="true"/>`
+#[derive(Debug, Clone, Default)]
+pub struct DockerVolume {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub source: String,
+    pub target: String,
+    pub read_only: bool,
+}
+
+///Limits for a step's container, `<resources cpus="0.5" memory="512m"/>`
+#[derive(Debug, Clone, Default)]
+pub struct DockerResources {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub cpus: Option<f64>,
+    ///In bytes
+    pub memory: Option<u64>,
+}
+
+impl DockerConnectionInfo {
+    ///The registry host, and port if given, that the image is pulled from. None means Docker Hub
+    pub fn registry(&self) -> Option<&str> {
+        self.image
+            .split_once('/')
+            .map(|(first, _)| first)
+            .filter(|first| is_registry(first))
+    }
+}
+
+///Certificates used to connect to a remote step executor over TLS.
+///Each is a path or reference resolved by the runtime, the system roots are used when `ca_cert` is not set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RemoteTls {
+    pub ca_cert: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -116,35 +1324,50 @@ pub enum DockerStepProvider {
     Custom { name: String, path: String },
     Dockerfile { path: String },
     DockerImage(DockerConnectionInfo),
-    Remote { host: String, port: Option<String> },
+    Remote {
+        host: String,
+        port: Option<String>,
+        ///Connect over TLS, set with `tls="true"` or any of the certificate attributes on the step
+        tls: Option<RemoteTls>,
+        ///Sent to the remote executor to authenticate, `auth-token="env:EXECUTOR_TOKEN"`
+        auth_token: Option<CredentialRef>,
+    },
+}
+
+impl DockerStepProvider {
+    ///A short name for the kind of provider, one of custom, dockerfile, image or remote
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DockerStepProvider::Custom { .. } => "custom",
+            DockerStepProvider::Dockerfile { .. } => "dockerfile",
+            DockerStepProvider::DockerImage(_) => "image",
+            DockerStepProvider::Remote { .. } => "remote",
+        }
+    }
 }
 
 impl FromStr for DockerStepProvider {
     type Err = String;
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let input = input.to_lowercase();
-        if input.ends_with("dockerfile") {
-            input
-                .strip_prefix("file:")
-                .unwrap_or("")
-                .strip_suffix("dockerfile")
+        //only the provider prefix is case insensitive, image tags, credentials and paths are kept as written
+        if let Some(input) = strip_suffix_ignore_case(input, "dockerfile") {
+            strip_prefix_ignore_case(input, "file:")
                 .map(|v| DockerStepProvider::Dockerfile {
                     path: v.strip_prefix("/").unwrap_or(v).strip_suffix("/").unwrap_or(v).to_string(),
                 })
                 .ok_or_else(|| "Unable to parse plugin provider as a Dockerfile source".to_string())
-        } else if input.starts_with("hypi:") {
-            let input = input.strip_prefix("hypi:").unwrap();
+        } else if let Some(input) = strip_prefix_ignore_case(input, "hypi:") {
             Ok(DockerStepProvider::DockerImage(parse_docker_image(input)?))
-        } else if input.starts_with("remote:") {
-            let input = input.strip_prefix("remote:").unwrap();
+        } else if let Some(input) = strip_prefix_ignore_case(input, "remote:") {
             let idx = input.find(":");
             Ok(DockerStepProvider::Remote {
                 host: input[0..idx.unwrap_or(input.len())].to_string(),
                 port: idx.map(|idx| input[idx + 1..].to_string()),
+                tls: None,
+                auth_token: None,
             })
-        } else if input.starts_with("docker:") {
-            let input = input.strip_prefix("docker:").unwrap();
+        } else if let Some(input) = strip_prefix_ignore_case(input, "docker:") {
             Ok(DockerStepProvider::DockerImage(parse_docker_image(input)?))
         } else {
             if input.contains(":") {
@@ -161,54 +1384,148 @@ impl FromStr for DockerStepProvider {
     }
 }
 
+fn strip_prefix_ignore_case<'a>(input: &'a str, prefix: &str) -> Option<&'a str> {
+    input.get(..prefix.len()).filter(|v| v.eq_ignore_ascii_case(prefix)).map(|_| &input[prefix.len()..])
+}
+
+fn strip_suffix_ignore_case<'a>(input: &'a str, suffix: &str) -> Option<&'a str> {
+    let idx = input.len().checked_sub(suffix.len())?;
+    input.get(idx..).filter(|v| v.eq_ignore_ascii_case(suffix)).map(|_| &input[..idx])
+}
+
+///Splits a trailing `@sha256:<digest>` off of an image reference
+fn split_digest(input: &str) -> Result<(&str, Option<String>), String> {
+    let idx = match input.rfind('@') {
+        Some(idx) if input[idx + 1..].starts_with("sha256:") => idx,
+        _ => return Ok((input, None)),
+    };
+    let digest = &input[idx + 1..];
+    let hex = &digest["sha256:".len()..];
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid image digest '{}', expected sha256: followed by 64 hex characters", digest));
+    }
+    Ok((&input[..idx], Some(digest.to_lowercase())))
+}
+
+///Parses an image reference in the form `[user:pass@][registry[:port]/]repository[/path...][:tag][@sha256:digest]`
+///following the Docker/OCI naming rules. The registry is only recognised if it contains a `.` or `:` or is `localhost`,
+///otherwise the first component is part of the repository e.g. `hypi/form` is on Docker Hub.
 pub fn parse_docker_image(input: &str) -> Result<DockerConnectionInfo, String> {
-    let (username, pass, image, tag) = if input.contains("@") {
-        let mut parts = input.split("@");
-        let user_and_pass = parts
-            .next()
-            .ok_or_else(|| "Provider with @ must be in the form user:pass@image:tag".to_string())?;
-        let mut user_and_pass = user_and_pass.split(":");
-        let user = user_and_pass
-            .next()
-            .ok_or_else(|| "Provider with @ must be in the form user:pass@image:tag".to_string())?;
-        let pass = user_and_pass
-            .next()
-            .ok_or_else(|| "Provider with @ must be in the form user:pass@image:tag".to_string())?;
-        let image_and_tag = parts
-            .next()
-            .ok_or_else(|| "Provider with @ must be in the form user:pass@image:tag".to_string())?;
-        let img = image_and_tag.chars().take_while(|v| v != &':').collect();
-        let tag = image_and_tag.split(":").last().map(|v| v.to_owned());
-        (Some(user), Some(pass), Some(img), Some(tag))
-    } else {
-        (None, None, None, None)
+    let (input, digest) = split_digest(input)?;
+    //image references can't contain @ so the last one separates the credentials
+    let (credentials, reference) = match input.rsplit_once('@') {
+        Some((credentials, reference)) => {
+            let (username, password) = credentials
+                .split_once(':')
+                .filter(|(user, pass)| !user.is_empty() && !pass.is_empty())
+                .ok_or_else(|| "Provider with @ must be in the form user:pass@image:tag".to_string())?;
+            (Some((username, password)), reference)
+        }
+        None => (None, input),
     };
+    let (image, tag) = split_tag(reference);
+    validate_image_name(image)?;
+    if let Some(tag) = tag {
+        validate_tag(tag)?;
+    }
+    if tag.is_some() && digest.is_some() {
+        return Err(format!("Image '{}' has both a tag and a digest, use one or the other", input));
+    }
     Ok(DockerConnectionInfo {
         start_pos: Default::default(),
         end_pos: Default::default(),
-        username: username.map(|v| v.to_owned()),
-        password: pass.map(|v| v.to_owned()),
-        image: if let Some(img) = image {
-            img
-        } else if input.contains(":") {
-            input.chars().take_while(|v| v != &':').collect()
-        } else {
-            input.to_owned()
-        },
-        tag:
-        if let Some(v) = tag {
-            v
-        } else {
-            if input.contains(":") {
-                input.split(":").last().map(|v| v.to_owned())
-            } else {
-                None
-            }
-        }
-        ,
+        username: credentials.map(|(user, _)| user.to_owned()),
+        password: credentials.map(|(_, pass)| pass.to_owned()),
+        image: image.to_owned(),
+        tag: tag.map(|v| v.to_owned()),
+        digest,
+        healthcheck: None,
+        build_args: vec![],
+        context: None,
+        cache: None,
+        credentials: credentials.map(|(username, password)| CredentialRef::Inline {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }),
     })
 }
 
+///The tag is after the last `:` but only if it comes after the last `/`, otherwise the `:` is a registry port
+fn split_tag(reference: &str) -> (&str, Option<&str>) {
+    let name_start = reference.rfind('/').map(|idx| idx + 1).unwrap_or(0);
+    match reference[name_start..].rfind(':') {
+        Some(idx) => (
+            &reference[..name_start + idx],
+            Some(&reference[name_start + idx + 1..]),
+        ),
+        None => (reference, None),
+    }
+}
+
+fn is_registry(component: &str) -> bool {
+    component.contains('.') || component.contains(':') || component == "localhost"
+}
+
+fn validate_image_name(image: &str) -> Result<(), String> {
+    if image.is_empty() {
+        return Err("The image name cannot be empty".to_string());
+    }
+    let mut components = image.split('/').peekable();
+    let first = components.next().unwrap_or("");
+    let has_path = components.peek().is_some();
+    if has_path && is_registry(first) {
+        let (host, port) = match first.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (first, None),
+        };
+        if host.is_empty()
+            || host.starts_with('-')
+            || !host.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        {
+            return Err(format!("Invalid registry '{}' in image '{}'", first, image));
+        }
+        if let Some(port) = port {
+            port.parse::<u16>()
+                .map_err(|_| format!("Invalid registry port '{}' in image '{}'", port, image))?;
+        }
+    } else if !is_path_component(first) {
+        return Err(format!("Invalid image name '{}', '{}' can only contain lowercase letters, digits and separators", image, first));
+    }
+    for component in components {
+        if !is_path_component(component) {
+            return Err(format!("Invalid image name '{}', '{}' can only contain lowercase letters, digits and separators", image, component));
+        }
+    }
+    Ok(())
+}
+
+///A repository path component is lowercase letters and digits, separated by `.`, `_`, `__` or one or more `-`
+fn is_path_component(component: &str) -> bool {
+    let is_alnum = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    if !component.starts_with(is_alnum) || !component.ends_with(is_alnum) {
+        return false;
+    }
+    component
+        .split(is_alnum)
+        .filter(|separator| !separator.is_empty())
+        .all(|separator| {
+            separator == "." || separator == "_" || separator == "__" || separator.chars().all(|c| c == '-')
+        })
+}
+
+fn validate_tag(tag: &str) -> Result<(), String> {
+    let valid = !tag.is_empty()
+        && tag.len() <= 128
+        && !tag.starts_with('.')
+        && !tag.starts_with('-')
+        && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid image tag '{}'", tag))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -249,6 +1566,10 @@ mod test {
                 assert_eq!(info.tag, Some("v2".to_string()));
                 assert_eq!(info.username, Some("user2".to_string()));
                 assert_eq!(info.password, Some("pass2".to_string()));
+                assert_eq!(
+                    info.credentials,
+                    Some(CredentialRef::Inline { username: "user2".to_string(), password: "pass2".to_string() })
+                );
             }
             _ => panic!("should've gotten a docker image")
         }
@@ -282,6 +1603,17 @@ mod test {
             }
             _ => panic!("should've gotten a docker image")
         }
+        let digest = format!("sha256:{}", "a".repeat(64));
+        match format!("hypi:repo.hypi.ai/rapid-plugin-form@{}", digest).parse()? {
+            DockerStepProvider::DockerImage(info) => {
+                assert_eq!(info.image, "repo.hypi.ai/rapid-plugin-form");
+                assert_eq!(info.tag, None);
+                assert_eq!(info.digest, Some(digest.clone()));
+            }
+            _ => panic!("should've gotten a docker image")
+        }
+        assert!(format!("hypi:form:v2@{}", digest).parse::<DockerStepProvider>().is_err());
+        assert!("hypi:form@sha256:abc".parse::<DockerStepProvider>().is_err());
         match "file:my-plugin/Dockerfile".parse()? {
             DockerStepProvider::Dockerfile { path } => {
                 assert_eq!(path, "my-plugin");
@@ -289,14 +1621,14 @@ mod test {
             _ => panic!("should've gotten a docker image")
         }
         match "remote:localhost:2020".parse()? {
-            DockerStepProvider::Remote { host, port } => {
+            DockerStepProvider::Remote { host, port, .. } => {
                 assert_eq!(host, "localhost");
                 assert_eq!(port, Some(2020.to_string()));
             }
             _ => panic!("should've gotten a remote host and port")
         }
         match "remote:localhost".parse()? {
-            DockerStepProvider::Remote { host, port } => {
+            DockerStepProvider::Remote { host, port, .. } => {
                 assert_eq!(host, "localhost");
                 assert_eq!(port, None);
             }
@@ -304,4 +1636,64 @@ mod test {
         }
         Ok(())
     }
+
+    #[test]
+    fn can_parse_oci_references() -> Result<(), String> {
+        let cases: &[(&str, &str, Option<&str>, Option<&str>)] = &[
+            ("form", "form", None, None),
+            ("form:1.2", "form", Some("1.2"), None),
+            ("hypi/form", "hypi/form", None, None),
+            ("localhost:5000/team/app:1.2", "localhost:5000/team/app", Some("1.2"), Some("localhost:5000")),
+            ("localhost:5000/team/app", "localhost:5000/team/app", None, Some("localhost:5000")),
+            ("localhost/app", "localhost/app", None, Some("localhost")),
+            ("repo.hypi.ai/a/b/c:v1", "repo.hypi.ai/a/b/c", Some("v1"), Some("repo.hypi.ai")),
+            ("gcr.io/my-project/my_app__x:latest", "gcr.io/my-project/my_app__x", Some("latest"), Some("gcr.io")),
+            ("my-registry.com:443/app.name", "my-registry.com:443/app.name", None, Some("my-registry.com:443")),
+        ];
+        for (input, image, tag, registry) in cases {
+            let info = parse_docker_image(input)?;
+            assert_eq!(info.image, *image, "{}", input);
+            assert_eq!(info.tag.as_deref(), *tag, "{}", input);
+            assert_eq!(info.registry(), *registry, "{}", input);
+        }
+        let info = parse_docker_image("user:p@ss@localhost:5000/team/app:1.2")?;
+        assert_eq!(info.username.as_deref(), Some("user"));
+        assert_eq!(info.password.as_deref(), Some("p@ss"));
+        assert_eq!(info.image, "localhost:5000/team/app");
+        let digest = format!("sha256:{}", "0".repeat(64));
+        let info = parse_docker_image(&format!("localhost:5000/app@{}", digest))?;
+        assert_eq!((info.image.as_str(), info.tag, info.digest), ("localhost:5000/app", None, Some(digest)));
+        for invalid in ["", ":tag", "app:", "App", "team//app", "-app", "app-", "a..b", "localhost:port/app", "app:-x", "@app", "user@app"] {
+            assert!(parse_docker_image(invalid).is_err(), "{} should be invalid", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn can_parse_step_positions() {
+        assert_eq!("last".parse(), Ok(ImplicitDockerStepPosition::Last));
+        assert_eq!("step:validate".parse(), Ok(ImplicitDockerStepPosition::Step("validate".to_owned())));
+        assert!("step:".parse::<ImplicitDockerStepPosition>().is_err());
+        assert!("middle".parse::<ImplicitDockerStepPosition>().is_err());
+    }
+
+    #[test]
+    fn can_place_implicit_steps() {
+        let steps = ["auth", "validate", "save"];
+        let before = |v: &str| StepPlacement { before: Some(v.parse().unwrap()), after: None };
+        let after = |v: &str| StepPlacement { before: None, after: Some(v.parse().unwrap()) };
+        assert_eq!(before("first").insertion_indices(&steps), vec![0]);
+        assert_eq!(before("each").insertion_indices(&steps), vec![0, 1, 2]);
+        assert_eq!(before("last").insertion_indices(&steps), vec![2]);
+        assert_eq!(before("step:validate").insertion_indices(&steps), vec![1]);
+        assert_eq!(after("first").insertion_indices(&steps), vec![1]);
+        assert_eq!(after("each").insertion_indices(&steps), vec![1, 2, 3]);
+        assert_eq!(after("last").insertion_indices(&steps), vec![3]);
+        assert_eq!(after("step:validate").insertion_indices(&steps), vec![2]);
+        assert!(after("step:missing").insertion_indices(&steps).is_empty());
+        assert!(StepPlacement::default().insertion_indices(&steps).is_empty());
+        assert!(before("first").insertion_indices(&[]).is_empty());
+        let around_each = StepPlacement { before: Some("each".parse().unwrap()), after: Some("each".parse().unwrap()) };
+        assert_eq!(around_each.insertion_indices(&steps), vec![0, 1, 1, 2, 2, 3]);
+    }
 }
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use crate::expression::{MappingExpr, PathSegment};
+use crate::graph::{DependencyGraph, Entity};
+use crate::haml_parser::{
+    Diagnostic, LintLevel, HAML_CODE_LINT_EMPTY_PIPELINE, HAML_CODE_LINT_MISSING_PRIMARY_KEY, HAML_CODE_LINT_NAMING,
+    HAML_CODE_LINT_NO_RESPONSES, HAML_CODE_LINT_NULLABLE_UNIQUE, HAML_CODE_LINT_UNUSED_ENV,
+    HAML_CODE_LINT_UNUSED_PIPELINE, HAML_CODE_LINT_UNUSED_TABLE,
+};
+use crate::manifested_schema::{endpoint_key, DocumentDef, Mapping, Middleware, Pipeline, Step, TableDef};
+use crate::{CredentialRef, DockerStepProvider, TableRef};
+
+///A check for something that's valid HAML but likely a mistake or against convention. Rules are off unless
+///[ParseOptions::lint_rules](crate::haml_parser::ParseOptions::lint_rules) or the document's `<lint>` element
+///gives their id a level, e.g. `<lint><rule code="haml_lint_naming" level="deny"/></lint>`.
+pub trait LintRule {
+    ///The code of the diagnostics the rule reports, rules are configured by it
+    fn id(&self) -> &str;
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic>;
+}
+
+impl Debug for dyn LintRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LintRule({})", self.id())
+    }
+}
+
+///The rules that come with HAML
+pub fn builtin_rules() -> Vec<Arc<dyn LintRule>> {
+    vec![
+        Arc::new(NamingConvention),
+        Arc::new(MissingPrimaryKey),
+        Arc::new(EndpointWithoutResponses),
+        Arc::new(NullableUniqueColumn),
+        Arc::new(EmptyPipeline),
+        Arc::new(UnusedEnvVar),
+        Arc::new(UnusedPipeline),
+        Arc::new(UnusedTable),
+    ]
+}
+
+///Reports the pipelines, tables and env vars nothing in the document uses, whatever level their rules are set to.
+///Useful to keep large documents clean, e.g. from an editor or CI step
+pub fn dead_code(doc: &DocumentDef) -> Vec<Diagnostic> {
+    let rules: [&dyn LintRule; 3] = [&UnusedPipeline, &UnusedTable, &UnusedEnvVar];
+    rules.iter().flat_map(|v| v.check(doc)).collect()
+}
+
+///Runs every rule in `rules` that `levels` doesn't leave off
+pub fn run(doc: &DocumentDef, rules: &[Arc<dyn LintRule>], levels: &HashMap<String, LintLevel>) -> Vec<Diagnostic> {
+    rules
+        .iter()
+        .filter(|v| matches!(levels.get(v.id()), Some(LintLevel::Warn | LintLevel::Deny)))
+        .flat_map(|v| v.check(doc))
+        .collect()
+}
+
+///Table and column names are snake_case
+pub struct NamingConvention;
+
+impl LintRule for NamingConvention {
+    fn id(&self) -> &str {
+        "haml_lint_naming"
+    }
+
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic> {
+        let is_snake_case = |name: &str| {
+            name.starts_with(|c: char| c.is_ascii_lowercase())
+                && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        };
+        let mut diagnostics = vec![];
+        for table in tables(doc) {
+            if !is_snake_case(&table.name) {
+                diagnostics.push(Diagnostic::warning(
+                    &table.start_pos,
+                    &HAML_CODE_LINT_NAMING,
+                    "table",
+                    format!("The table name '{}' is not snake_case.", table.name),
+                ));
+            }
+            for column in table.columns.iter().filter(|v| !is_snake_case(&v.name)) {
+                diagnostics.push(Diagnostic::warning(
+                    &column.start_pos,
+                    &HAML_CODE_LINT_NAMING,
+                    "column",
+                    format!("The column name '{}' is not snake_case.", column.name),
+                ));
+            }
+        }
+        diagnostics
+    }
+}
+
+///Every table has a primary key column
+pub struct MissingPrimaryKey;
+
+impl LintRule for MissingPrimaryKey {
+    fn id(&self) -> &str {
+        "haml_lint_missing_primary_key"
+    }
+
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic> {
+        tables(doc)
+            .filter(|v| !v.columns.iter().any(|v| v.primary_key))
+            .map(|v| {
+                Diagnostic::warning(
+                    &v.start_pos,
+                    &HAML_CODE_LINT_MISSING_PRIMARY_KEY,
+                    "table",
+                    format!("The table '{}' has no primary key column.", v.name),
+                )
+            })
+            .collect()
+    }
+}
+
+///Declared endpoints say what they respond with
+pub struct EndpointWithoutResponses;
+
+impl LintRule for EndpointWithoutResponses {
+    fn id(&self) -> &str {
+        "haml_lint_endpoint_without_responses"
+    }
+
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic> {
+        doc.rest
+            .iter()
+            .flat_map(|v| v.endpoints.iter())
+            .filter(|v| v.responses.is_empty())
+            .map(|v| {
+                Diagnostic::warning(
+                    &v.start_pos,
+                    &HAML_CODE_LINT_NO_RESPONSES,
+                    "endpoint",
+                    format!(
+                        "The endpoint '{}' doesn't declare any responses.",
+                        endpoint_key(&v.method, v.path.as_deref().unwrap_or(""))
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+///Unique columns aren't nullable, most databases allow any number of nulls in a unique column
+pub struct NullableUniqueColumn;
+
+impl LintRule for NullableUniqueColumn {
+    fn id(&self) -> &str {
+        "haml_lint_nullable_unique"
+    }
+
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic> {
+        tables(doc)
+            .flat_map(|table| table.columns.iter().map(move |column| (table, column)))
+            .filter(|(_, column)| column.unique && column.nullable && !column.primary_key)
+            .map(|(table, column)| {
+                Diagnostic::warning(
+                    &column.start_pos,
+                    &HAML_CODE_LINT_NULLABLE_UNIQUE,
+                    "column",
+                    format!(
+                        "The column '{}.{}' is unique but nullable, any number of rows can leave it empty.",
+                        table.name, column.name
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+///Declared pipelines have at least one step
+pub struct EmptyPipeline;
+
+impl LintRule for EmptyPipeline {
+    fn id(&self) -> &str {
+        "haml_lint_empty_pipeline"
+    }
+
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic> {
+        declared_pipelines(doc)
+            .filter(|v| v.steps.is_empty())
+            .map(|v| {
+                Diagnostic::warning(
+                    &v.start_pos,
+                    &HAML_CODE_LINT_EMPTY_PIPELINE,
+                    "pipeline",
+                    format!("The pipeline '{}' has no steps.", v.name),
+                )
+            })
+            .collect()
+    }
+}
+
+///Env vars are used as `${env.NAME}` in a mapping or step env var, as `env:NAME` credentials of a step or as the
+///endpoint-env of tracing
+pub struct UnusedEnvVar;
+
+impl LintRule for UnusedEnvVar {
+    fn id(&self) -> &str {
+        "haml_lint_unused_env"
+    }
+
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic> {
+        let mut used = vec![];
+        for step in declared_pipelines(doc).flat_map(|v| v.steps.iter()) {
+            env_references(step.mappings(), &mut used);
+            let step = match step {
+                Step::Docker(step) => step,
+                _ => continue,
+            };
+            for env in &step.env {
+                used.extend(
+                    doc.env
+                        .iter()
+                        .filter(|v| env.value.contains(&format!("${{env.{}}}", v.name)))
+                        .map(|v| v.name.clone()),
+                );
+            }
+            let credentials = match &step.provider {
+                DockerStepProvider::DockerImage(info) => info.credentials.as_ref(),
+                DockerStepProvider::Remote { auth_token, .. } => auth_token.as_ref(),
+                _ => None,
+            };
+            if let Some(CredentialRef::Env(name)) = credentials {
+                used.push(name.clone());
+            }
+        }
+        if let Some(tracing) = doc.observability.as_ref().and_then(|v| v.tracing.as_ref()) {
+            used.push(tracing.endpoint_env.clone());
+        }
+        doc.env
+            .iter()
+            .filter(|v| !used.contains(&v.name))
+            .map(|v| {
+                Diagnostic::warning(
+                    &v.start_pos,
+                    &HAML_CODE_LINT_UNUSED_ENV,
+                    "env",
+                    format!("The env var '{}' is not used by any mapping or step.", v.name),
+                )
+            })
+            .collect()
+    }
+}
+
+///Pipelines declared under apis are run by an endpoint, job, GraphQL resolver, middleware or call step
+pub struct UnusedPipeline;
+
+impl LintRule for UnusedPipeline {
+    fn id(&self) -> &str {
+        "haml_lint_unused_pipeline"
+    }
+
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic> {
+        let mut used: Vec<&str> = doc.endpoints().map(|v| v.pipeline.name.as_str()).collect();
+        used.extend(doc.jobs.iter().map(|v| v.pipeline.as_str()));
+        used.extend(doc.graphql.iter().flat_map(|v| v.resolvers.iter()).map(|v| v.pipeline.as_str()));
+        used.extend(doc.middleware.iter().filter_map(|v| match &v.runs {
+            Middleware::Pipeline(name) => Some(name.as_str()),
+            Middleware::Step(_) => None,
+        }));
+        for step in declared_pipelines(doc).flat_map(|v| v.steps.iter()) {
+            if let Step::Call(step) = step {
+                if let Some(pipeline) = doc.call_target_pipeline(&step.target) {
+                    used.push(&pipeline.name);
+                }
+            }
+        }
+        doc.pipelines
+            .iter()
+            .filter(|v| !v.name.is_empty() && !used.contains(&v.name.as_str()))
+            .map(|v| {
+                Diagnostic::warning(
+                    &v.start_pos,
+                    &HAML_CODE_LINT_UNUSED_PIPELINE,
+                    "pipeline",
+                    format!(
+                        "The pipeline '{}' is not run by any endpoint, job, resolver, middleware or call step.",
+                        v.name
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+///Tables have CRUD enabled, are mapped by a pipeline, queried by a sql step or referenced by another table's foreign
+///key. Sql steps use a table if their SQL mentions its name, well known tables are used by the platform
+pub struct UnusedTable;
+
+impl LintRule for UnusedTable {
+    fn id(&self) -> &str {
+        "haml_lint_unused_table"
+    }
+
+    fn check(&self, doc: &DocumentDef) -> Vec<Diagnostic> {
+        let graph = DependencyGraph::from(doc);
+        let crud: Vec<&TableRef> = doc.crud_enabled_tables.iter().filter_map(|v| doc.resolve_table(v)).collect();
+        let sql: Vec<String> = declared_pipelines(doc)
+            .flat_map(|v| v.steps.iter())
+            .filter_map(|v| match v {
+                Step::Sql(step) => Some(step.sql.to_lowercase()),
+                _ => None,
+            })
+            .collect();
+        let mut diagnostics = vec![];
+        for db in &doc.databases {
+            for schema in &db.schemas {
+                for table in schema.tables.iter().filter(|v| v.hypi.as_ref().is_none_or(|v| v.well_known.is_none())) {
+                    let table_ref = TableRef {
+                        db: db.name.clone(),
+                        schema: schema.name.clone(),
+                        table: table.name.clone(),
+                    };
+                    let qualified_name = table_ref.qualified_name();
+                    //a table's foreign keys to its own columns don't count
+                    let referenced = graph.dependents(&Entity::Table(qualified_name.clone())).iter().any(|v| match v {
+                        Entity::Column { table, .. } => table != &qualified_name,
+                        _ => true,
+                    });
+                    let name = table.name.to_lowercase();
+                    let queried = sql.iter().any(|v| {
+                        v.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                            .any(|word| word.rsplit('.').next() == Some(name.as_str()))
+                    });
+                    if !(referenced || queried || crud.contains(&&table_ref)) {
+                        diagnostics.push(Diagnostic::warning(
+                            &table.start_pos,
+                            &HAML_CODE_LINT_UNUSED_TABLE,
+                            "table",
+                            format!(
+                                "The table '{}' is not used by any CRUD option, pipeline, sql step or foreign key.",
+                                qualified_name
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        diagnostics
+    }
+}
+
+fn tables(doc: &DocumentDef) -> impl Iterator<Item=&TableDef> {
+    doc.databases
+        .iter()
+        .flat_map(|v| v.schemas.iter())
+        .flat_map(|v| v.tables.iter())
+}
+
+///Pipelines written in the document, not the ones generated for CRUD and core API endpoints
+fn declared_pipelines(doc: &DocumentDef) -> impl Iterator<Item=&Pipeline> {
+    let endpoints = doc.rest.iter().flat_map(|v| v.endpoints.iter());
+    doc.pipelines.iter().chain(endpoints.map(|v| &v.pipeline))
+}
+
+fn env_references(mappings: &[Mapping], used: &mut Vec<String>) {
+    for mapping in mappings {
+        if let Some(MappingExpr::Variable { root, path }) = &mapping.expr {
+            match path.first() {
+                Some(PathSegment::Field(name)) if root == "env" => used.push(name.clone()),
+                _ => {}
+            }
+        }
+        env_references(&mapping.children, used);
+    }
+}
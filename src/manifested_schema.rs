@@ -1,10 +1,20 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+
 use rapid_utils::http_utils::HttpMethod;
 
 use crate::{
-    CoreApi, DatabaseType, DockerConnectionInfo, DockerStepProvider, ImplicitDockerStepPosition,
-    Location, TableConstraintType,
+    CoreApi, CredentialRef, DatabaseType, DockerConnectionInfo, DockerHealthcheck, DockerPort, DockerResources, DockerStepProvider,
+    DockerVolume, HamlVersion, HttpStatus, ImplicitDockerStepPosition, Location, LockoutPolicy, MediaType, StorageConfig, MessageProvider, TotpConfig, DbReplica, DbTlsConfig, PoolConfig, PullPolicy, ReadPreference, LogPolicy, ObservabilityConfig, RequestLimits, ScriptLimits, ScriptType, StepPlacement, TableConstraintType, TableRef, ValidationMode, MappingTransform, ColumnValidation, MaskPolicy, ColumnEncryption,
 };
-use crate::haml_parser::{ColumnDefault, ColumnType, ParsedColumn, ParsedColumnPipeline, ParsedConstraint, ParsedDb, ParsedDockerStep, ParsedDocument, ParsedEndpoint, ParsedEndpointResponse,  ParsedEnv, ParsedGraphQL, ParsedHypi, ParsedJob, ParsedKeyValuePair, ParsedMapping, ParsedMeta, ParsedPipeline, ParsedRest, ParsedSchema, ParsedTable, WellKnownType};
+use crate::expression::{Condition, MappingExpr, PathSegment, YieldRange};
+use crate::template::TemplateEngine;
+use crate::fingerprint::{Fingerprint, Fingerprinter};
+use crate::haml_parser::{ColumnDefault, ColumnType, ParsedColumn, ParsedColumnPipeline, ParsedConstraint, ParsedDb, ParsedDockerStep, ParsedDocument, ParsedEndpoint, ParsedEndpointResponse, ParsedErrorMapping, ParsedErrors,  ParsedEnv, ParsedGraphQL, ParsedHypi, ParsedResolver, ParsedFederationKey, GraphQLOperation, ParsedJob, ParsedKeyValuePair, ParsedMapping, ParsedApiVersion, ParsedFeature, ParsedMeta, ParsedMiddleware, ParsedCache, ParsedPipeline, ParsedPipelineField, ParsedCallStep, ParsedFnStep, ParsedRest, ParsedScriptStep, ParsedSqlStep, ParsedStep, ParsedSchema, ParsedSearchIndex, ParsedTable, WellKnownType};
 
 #[derive(Clone, Debug)]
 pub struct DocumentDef {
@@ -12,19 +22,443 @@ pub struct DocumentDef {
     pub end_pos: Location,
     pub crud_enabled_tables: Vec<String>,
     pub enabled_core_apis: Vec<CoreApi>,
+    ///Options set on the core-api elements, keyed by option name e.g. `token-ttl`
+    pub core_api_options: HashMap<CoreApi, HashMap<String, String>>,
+    pub sms_provider: Option<MessageProvider>,
+    pub email_provider: Option<MessageProvider>,
+    ///Only set when the 2fa-totp core API is enabled
+    pub totp: Option<TotpConfig>,
+    pub lockout_policy: Option<LockoutPolicy>,
     pub rest: Option<RestApiDef>,
     pub graphql: Option<GraphQLApiDef>,
+    ///The pipelines declared under apis, endpoint pipelines are on [EndpointDef::pipeline]
+    pub pipelines: Vec<Pipeline>,
     pub jobs: Vec<JobDef>,
+    ///The middleware endpoints can use, see [EndpointDef::middleware]
+    pub middleware: Vec<MiddlewareDef>,
+    ///The feature flags endpoints, pipelines and jobs can be put behind, see [DocumentDef::with_features]
+    pub features: Vec<FeatureDef>,
     pub databases: Vec<DatabaseDef>,
     pub env: Vec<EnvVar>,
     pub step_builders: Vec<DockerConnectionInfo>,
+    ///The docker steps of global-options that run in every pipeline, where is given by [DockerStep::placement]
+    pub implicit_steps: Vec<DockerStep>,
     pub meta: MetaDef,
+    ///The HAML version declared by the document, None if it doesn't declare one
+    pub haml_version: Option<HamlVersion>,
+    ///Where uploaded files are kept, required when a table uses the well-known file type
+    pub storage: Option<StorageConfig>,
+    ///Tracing, metrics and logging settings for the runtime
+    pub observability: Option<ObservabilityConfig>,
+    ///Every declared table, keyed by its fully qualified name `db.schema.table`
+    pub table_index: HashMap<String, TableRef>,
+    ///The endpoints generated for every table with CRUD enabled, see [crud_endpoints]
+    pub crud_endpoints: Vec<EndpointDef>,
+    ///The endpoints of the enabled core APIs, see [core_api_endpoints]
+    pub core_api_endpoints: Vec<EndpointDef>,
+    ///Error mappings applied to every endpoint, see [EndpointDef::error_mappings]
+    pub error_mappings: Vec<ErrorMappingDef>,
+    ///The validation used by endpoints that don't set their own
+    pub validation: ValidationMode,
+    ///The limits used by endpoints that don't set their own
+    pub request_limits: RequestLimits,
+    index: DocumentIndex,
+}
+
+impl DocumentDef {
+    ///Finds the table `name` refers to, `name` can be `table`, `schema.table` or `db.schema.table`.
+    ///Returns None if no table or more than one table matches.
+    pub fn resolve_table(&self, name: &str) -> Option<&TableRef> {
+        resolve_table_in(&self.table_index, name)
+    }
+
+    ///Every endpoint the document exposes, declared and generated
+    pub fn endpoints(&self) -> impl Iterator<Item=&EndpointDef> {
+        self.rest
+            .iter()
+            .flat_map(|v| v.endpoints.iter())
+            .chain(self.crud_endpoints.iter())
+            .chain(self.core_api_endpoints.iter())
+    }
+
+    ///The table `name` refers to, see [DocumentDef::resolve_table] for the forms `name` can take
+    pub fn find_table(&self, name: &str) -> Option<&TableDef> {
+        let (db, schema, table) = *self.index.tables.get(&self.resolve_table(name)?.qualified_name())?;
+        Some(&self.databases[db].schemas[schema].tables[table])
+    }
+
+    ///The endpoint, declared or generated, with the given method and path. Leading and trailing slashes are ignored
+    pub fn find_endpoint(&self, method: &HttpMethod, path: &str) -> Option<&EndpointDef> {
+        let i = *self.index.endpoints.get(&endpoint_key(method, path))?;
+        self.endpoints().nth(i)
+    }
+
+    ///The pipeline a call step with `target` runs, none for core APIs or targets the document doesn't have
+    pub fn call_target_pipeline(&self, target: &CallTarget) -> Option<&Pipeline> {
+        match target {
+            CallTarget::Endpoint { name, method } => self
+                .endpoints()
+                .find(|v| v.name.as_ref() == Some(name) && endpoint_key(&v.method, "") == endpoint_key(method, ""))
+                .map(|v| &v.pipeline),
+            CallTarget::Pipeline(name) => self.find_pipeline(name),
+            CallTarget::CoreApi(_) => None,
+        }
+    }
+
+    ///The pipeline called `name`, looking at the pipelines under apis before those of endpoints
+    pub fn find_pipeline(&self, name: &str) -> Option<&Pipeline> {
+        let i = *self.index.pipelines.get(name)?;
+        self.all_pipelines().nth(i)
+    }
+
+    ///Every table in every schema of the database labelled `label`
+    pub fn tables_in_db(&self, label: &str) -> Vec<&TableDef> {
+        self.databases
+            .iter()
+            .filter(|v| v.name == label)
+            .flat_map(|v| v.schemas.iter())
+            .flat_map(|v| v.tables.iter())
+            .collect()
+    }
+
+    ///Every endpoint whose pipeline is called `name`
+    pub fn endpoints_using_pipeline(&self, name: &str) -> Vec<&EndpointDef> {
+        self.endpoints().filter(|v| v.pipeline.name == name).collect()
+    }
+
+    fn all_pipelines(&self) -> impl Iterator<Item=&Pipeline> {
+        self.pipelines.iter().chain(self.endpoints().map(|v| &v.pipeline))
+    }
+
+    ///Counts what the document declares, e.g. to show on a dashboard or check against plan limits
+    pub fn stats(&self) -> DocumentStats {
+        let mut stats = DocumentStats {
+            databases: self.databases.len(),
+            jobs: self.jobs.len(),
+            ..DocumentStats::default()
+        };
+        for table in self.databases.iter().flat_map(|v| v.schemas.iter()).flat_map(|v| v.tables.iter()) {
+            stats.tables += 1;
+            stats.columns += table.columns.len();
+            stats.max_columns_per_table = stats.max_columns_per_table.max(table.columns.len());
+            for column in &table.columns {
+                *stats.columns_by_type.entry(column.typ.clone()).or_default() += 1;
+            }
+        }
+        for endpoint in self.endpoints() {
+            stats.endpoints += 1;
+            *stats
+                .endpoints_by_method
+                .entry(format!("{:?}", endpoint.method).to_uppercase())
+                .or_default() += 1;
+        }
+        //generated endpoints don't have a pipeline of their own
+        let endpoint_pipelines = self
+            .rest
+            .iter()
+            .flat_map(|v| v.endpoints.iter())
+            .map(|v| &v.pipeline);
+        for pipeline in self.pipelines.iter().chain(endpoint_pipelines) {
+            stats.pipelines += 1;
+            stats.steps += pipeline.steps.len();
+            stats.max_steps_per_pipeline = stats.max_steps_per_pipeline.max(pipeline.steps.len());
+            for step in &pipeline.steps {
+                *stats.steps_by_kind.entry(step.kind().to_owned()).or_default() += 1;
+            }
+        }
+        stats
+    }
+
+    ///A canonical copy of the document for hashing, de-duplicating and sharing. Identifiers, i.e. the names of
+    ///databases, schemas, tables, columns, pipelines, steps and jobs and the references to them, are lower cased.
+    ///Imports are already inlined and comments dropped by parsing. If `redact` is true, database passwords and
+    ///connection strings and docker registry credentials are replaced with [REDACTED].
+    pub fn normalized(&self, redact: bool) -> DocumentDef {
+        let mut doc = self.clone();
+        for db in &mut doc.databases {
+            db.name = db.name.to_lowercase();
+            if redact {
+                db.password = REDACTED.to_owned();
+                db.url = db.url.as_ref().map(|_| REDACTED.to_owned());
+            }
+            for schema in &mut db.schemas {
+                schema.name = schema.name.to_lowercase();
+                for table in &mut schema.tables {
+                    table.normalize();
+                }
+            }
+        }
+        lowercase_all(&mut doc.crud_enabled_tables);
+        for job in &mut doc.jobs {
+            job.name = job.name.to_lowercase();
+            job.pipeline = job.pipeline.to_lowercase();
+        }
+        if let Some(graphql) = &mut doc.graphql {
+            for resolver in &mut graphql.resolvers {
+                resolver.pipeline = resolver.pipeline.to_lowercase();
+            }
+        }
+        let endpoint_middleware = doc.rest.iter_mut().flat_map(|v| v.endpoints.iter_mut()).flat_map(|v| v.middleware.iter_mut());
+        for middleware in doc.middleware.iter_mut().chain(endpoint_middleware) {
+            match &mut middleware.runs {
+                Middleware::Pipeline(pipeline) => *pipeline = pipeline.to_lowercase(),
+                Middleware::Step(step) => {
+                    step.name = step.name.to_lowercase();
+                    if let (true, DockerStepProvider::DockerImage(info)) = (redact, &mut step.provider) {
+                        redact_registry_credentials(info);
+                    }
+                }
+            }
+        }
+        let endpoints = doc
+            .rest
+            .iter_mut()
+            .flat_map(|v| v.endpoints.iter_mut())
+            .chain(doc.crud_endpoints.iter_mut())
+            .chain(doc.core_api_endpoints.iter_mut());
+        for pipeline in doc.pipelines.iter_mut().chain(endpoints.map(|v| &mut v.pipeline)) {
+            pipeline.name = pipeline.name.to_lowercase();
+            for step in &mut pipeline.steps {
+                let name = step.name_mut();
+                *name = name.to_lowercase();
+                if let (true, Step::Docker(step)) = (redact, step) {
+                    if let DockerStepProvider::DockerImage(info) = &mut step.provider {
+                        redact_registry_credentials(info);
+                    }
+                }
+            }
+        }
+        for step in &mut doc.implicit_steps {
+            step.name = step.name.to_lowercase();
+            let positions = [&mut step.placement.before, &mut step.placement.after];
+            for position in positions.into_iter().flatten() {
+                if let ImplicitDockerStepPosition::Step(name) = position {
+                    *name = name.to_lowercase();
+                }
+            }
+            if let (true, DockerStepProvider::DockerImage(info)) = (redact, &mut step.provider) {
+                redact_registry_credentials(info);
+            }
+        }
+        if redact {
+            doc.step_builders.iter_mut().for_each(redact_registry_credentials);
+        }
+        doc.reindex();
+        doc
+    }
+
+    ///Whether `feature` is enabled by `flags`, or by default if `flags` doesn't have it. Features the document doesn't
+    ///declare are never enabled
+    pub fn is_feature_enabled(&self, feature: &str, flags: &HashMap<String, bool>) -> bool {
+        match self.features.iter().find(|v| v.name == feature) {
+            Some(declared) => flags.get(feature).copied().unwrap_or(declared.default),
+            None => false,
+        }
+    }
+
+    ///A copy of the document without the endpoints, pipelines and jobs behind features `flags` disables, see
+    ///[DocumentDef::is_feature_enabled]. Jobs running a pipeline that's removed are removed with it. The flag
+    ///associations are kept so the copy can be filtered again.
+    pub fn with_features(&self, flags: &HashMap<String, bool>) -> DocumentDef {
+        let enabled = |feature: &Option<String>| {
+            feature.as_ref().map(|v| self.is_feature_enabled(v, flags)).unwrap_or(true)
+        };
+        let mut doc = self.clone();
+        if let Some(rest) = &mut doc.rest {
+            rest.endpoints.retain(|v| enabled(&v.feature) && enabled(&v.pipeline.feature));
+        }
+        doc.pipelines.retain(|v| enabled(&v.feature));
+        let pipelines: Vec<_> = doc.pipelines.iter().map(|v| v.name.clone()).collect();
+        doc.jobs.retain(|v| {
+            let disabled = self.pipelines.iter().any(|p| p.name == v.pipeline) && !pipelines.contains(&v.pipeline);
+            enabled(&v.feature) && !disabled
+        });
+        doc.reindex();
+        doc
+    }
+
+    ///Rebuilds [DocumentDef::table_index] and the lookup indexes after the document is changed
+    pub(crate) fn reindex(&mut self) {
+        self.table_index.clear();
+        for db in &self.databases {
+            for schema in &db.schemas {
+                for table in &schema.tables {
+                    let table_ref = TableRef {
+                        db: db.name.clone(),
+                        schema: schema.name.clone(),
+                        table: table.name.clone(),
+                    };
+                    self.table_index.insert(table_ref.qualified_name(), table_ref);
+                }
+            }
+        }
+        self.index = DocumentIndex::new(self);
+    }
+
+    ///A hash of what the document declares. Documents that only differ in whitespace, attribute order, comments
+    ///or where things are declared have the same fingerprint so deployment systems can skip redeploying them.
+    pub fn fingerprint(&self) -> String {
+        //destructured without `..` so a new field has to be hashed, or deliberately skipped, here
+        let DocumentDef {
+            start_pos: _,
+            end_pos: _,
+            crud_enabled_tables,
+            enabled_core_apis,
+            core_api_options,
+            sms_provider,
+            email_provider,
+            totp,
+            lockout_policy,
+            rest,
+            graphql,
+            pipelines,
+            jobs,
+            middleware,
+            features,
+            databases,
+            env,
+            step_builders,
+            implicit_steps,
+            meta,
+            haml_version,
+            storage,
+            observability,
+            //the indexes are derived from the rest of the document
+            table_index: _,
+            crud_endpoints,
+            core_api_endpoints,
+            error_mappings,
+            validation,
+            request_limits,
+            index: _,
+        } = self;
+        let mut f = Fingerprinter::default();
+        crud_enabled_tables.fingerprint_into(&mut f);
+        enabled_core_apis.fingerprint_into(&mut f);
+        core_api_options.fingerprint_into(&mut f);
+        sms_provider.fingerprint_into(&mut f);
+        email_provider.fingerprint_into(&mut f);
+        totp.fingerprint_into(&mut f);
+        lockout_policy.fingerprint_into(&mut f);
+        rest.fingerprint_into(&mut f);
+        graphql.fingerprint_into(&mut f);
+        pipelines.fingerprint_into(&mut f);
+        jobs.fingerprint_into(&mut f);
+        middleware.fingerprint_into(&mut f);
+        features.fingerprint_into(&mut f);
+        databases.fingerprint_into(&mut f);
+        env.fingerprint_into(&mut f);
+        step_builders.fingerprint_into(&mut f);
+        implicit_steps.fingerprint_into(&mut f);
+        meta.fingerprint_into(&mut f);
+        haml_version.fingerprint_into(&mut f);
+        storage.fingerprint_into(&mut f);
+        observability.fingerprint_into(&mut f);
+        crud_endpoints.fingerprint_into(&mut f);
+        core_api_endpoints.fingerprint_into(&mut f);
+        error_mappings.fingerprint_into(&mut f);
+        validation.fingerprint_into(&mut f);
+        request_limits.fingerprint_into(&mut f);
+        format!("{:016x}", f.finish())
+    }
+}
+
+///What [DocumentDef::normalized] replaces credentials with
+pub const REDACTED: &str = "REDACTED";
+
+fn lowercase_all(values: &mut [String]) {
+    for value in values {
+        *value = value.to_lowercase();
+    }
+}
+
+fn redact_registry_credentials(info: &mut DockerConnectionInfo) {
+    info.username = info.username.as_ref().map(|_| REDACTED.to_owned());
+    info.password = info.password.as_ref().map(|_| REDACTED.to_owned());
+    if let Some(CredentialRef::Inline { username, password }) = &mut info.credentials {
+        *username = REDACTED.to_owned();
+        *password = REDACTED.to_owned();
+    }
+}
+
+///See [DocumentDef::stats]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentStats {
+    pub databases: usize,
+    pub tables: usize,
+    pub columns: usize,
+    pub columns_by_type: HashMap<ColumnType, usize>,
+    ///Declared, CRUD and core API endpoints
+    pub endpoints: usize,
+    ///Keyed by the upper case method e.g. `GET`
+    pub endpoints_by_method: HashMap<String, usize>,
+    pub pipelines: usize,
+    pub steps: usize,
+    ///Keyed by [DockerStepProvider::kind]
+    pub steps_by_kind: HashMap<String, usize>,
+    pub jobs: usize,
+    ///The most columns in any one table
+    pub max_columns_per_table: usize,
+    ///The most steps in any one pipeline
+    pub max_steps_per_pipeline: usize,
+}
+
+///Positions used by the lookups on [DocumentDef], built once when the definition is created
+#[derive(Clone, Debug, Default)]
+struct DocumentIndex {
+    ///The db, schema and table position of each table keyed by its qualified name
+    tables: HashMap<String, (usize, usize, usize)>,
+    ///The position in [DocumentDef::endpoints], see [endpoint_key]
+    endpoints: HashMap<String, usize>,
+    ///The position in [DocumentDef::all_pipelines]
+    pipelines: HashMap<String, usize>,
+}
+
+impl DocumentIndex {
+    fn new(doc: &DocumentDef) -> Self {
+        let mut index = DocumentIndex::default();
+        for (i, db) in doc.databases.iter().enumerate() {
+            for (j, schema) in db.schemas.iter().enumerate() {
+                for (k, table) in schema.tables.iter().enumerate() {
+                    let qualified_name = format!("{}.{}.{}", db.name, schema.name, table.name);
+                    index.tables.insert(qualified_name, (i, j, k));
+                }
+            }
+        }
+        for (i, endpoint) in doc.endpoints().enumerate() {
+            let key = endpoint_key(&endpoint.method, endpoint.path.as_deref().unwrap_or(""));
+            index.endpoints.entry(key).or_insert(i);
+        }
+        for (i, pipeline) in doc.all_pipelines().enumerate() {
+            index.pipelines.entry(pipeline.name.clone()).or_insert(i);
+        }
+        index
+    }
+}
+
+pub(crate) fn endpoint_key(method: &HttpMethod, path: &str) -> String {
+    format!("{} {}", format!("{:?}", method).to_uppercase(), path.trim_matches('/'))
+}
+
+///The names of the parameters in a path, `id` for both `account/{id}` and `account/:id`
+pub(crate) fn path_parameters(path: &str) -> impl Iterator<Item=&str> {
+    path.split('/')
+        .filter_map(|v| v.strip_prefix(':').or_else(|| v.strip_prefix('{')?.strip_suffix('}')))
+}
+
+///The path with every parameter, `{id}` or `:id`, replaced by `{}` so paths that route the same requests are equal
+pub(crate) fn route_pattern(path: &str) -> String {
+    path.trim_matches('/')
+        .split('/')
+        .map(|v| if v.starts_with(':') || (v.starts_with('{') && v.ends_with('}')) { "{}" } else { v })
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
 impl From<&ParsedDocument> for DocumentDef {
     fn from(value: &ParsedDocument) -> Self {
         let apis = &*value.apis.borrow();
-        let doc = DocumentDef {
+        let mut doc = DocumentDef {
             start_pos: value.start_pos.clone(),
             end_pos: value.end_pos.clone(),
             crud_enabled_tables: apis
@@ -37,12 +471,36 @@ impl From<&ParsedDocument> for DocumentDef {
                 .as_ref()
                 .map(|v| (&*v.borrow()).core_apis.clone())
                 .unwrap_or_else(|| vec![]),
+            core_api_options: apis
+                .global_options
+                .as_ref()
+                .map(|v| v.borrow().core_api_options.clone())
+                .unwrap_or_default(),
+            sms_provider: apis
+                .global_options
+                .as_ref()
+                .and_then(|v| v.borrow().sms_provider.as_ref().map(|v| v.borrow().clone())),
+            email_provider: apis
+                .global_options
+                .as_ref()
+                .and_then(|v| v.borrow().email_provider.as_ref().map(|v| v.borrow().clone())),
+            totp: apis.global_options.as_ref().and_then(|v| v.borrow().totp.clone()),
+            lockout_policy: apis
+                .global_options
+                .as_ref()
+                .and_then(|v| v.borrow().lockout.as_ref().map(|v| v.borrow().clone())),
             rest: apis.rest.as_ref().map(|v| (&*v.borrow()).into()),
             graphql: apis.graphql.as_ref().map(|v| (&*v.borrow()).into()),
+            pipelines: (&*apis.pipelines.borrow())
+                .iter()
+                .map(|v| (&*v.borrow()).into())
+                .collect(),
             jobs: (&*apis.jobs.borrow())
                 .iter()
                 .map(|v| (&*v.borrow()).into())
                 .collect(),
+            middleware: apis.middleware.iter().map(|v| (&*v.borrow()).into()).collect(),
+            features: apis.features.iter().map(|v| (&*v.borrow()).into()).collect(),
             databases: (&*value.databases.borrow())
                 .iter()
                 .map(|v| (&*v.borrow()).into())
@@ -55,8 +513,85 @@ impl From<&ParsedDocument> for DocumentDef {
                 .iter()
                 .map(|v| (&*v.borrow()).clone())
                 .collect(),
+            implicit_steps: apis
+                .global_options
+                .as_ref()
+                .map(|v| v.borrow().implicit_steps.borrow().iter().map(|v| (&*v.borrow()).into()).collect())
+                .unwrap_or_default(),
             meta: (&*value.meta.borrow()).into(),
+            haml_version: value.haml_version,
+            storage: value.storage.as_ref().map(|v| v.borrow().clone()),
+            observability: value.observability.as_ref().map(|v| v.borrow().clone()),
+            error_mappings: error_mappings(&apis.errors),
+            table_index: value
+                .table_refs()
+                .into_iter()
+                .map(|v| (v.qualified_name(), v))
+                .collect(),
+            crud_endpoints: vec![],
+            core_api_endpoints: vec![],
+            validation: apis
+                .global_options
+                .as_ref()
+                .and_then(|v| v.borrow().validation)
+                .unwrap_or_default(),
+            request_limits: apis
+                .global_options
+                .as_ref()
+                .map(|v| v.borrow().request_limits)
+                .unwrap_or_default(),
+            index: DocumentIndex::default(),
         };
+        if let (Some(rest), Some(parsed)) = (&mut doc.rest, &apis.rest) {
+            for (endpoint, parsed) in rest.endpoints.iter_mut().zip(parsed.borrow().all_endpoints()) {
+                let parsed = parsed.borrow();
+                if parsed.validation.is_none() {
+                    endpoint.validation = doc.validation;
+                }
+                endpoint.limits = parsed.limits.or(&doc.request_limits);
+                endpoint.middleware = parsed
+                    .middleware
+                    .iter()
+                    .filter_map(|name| doc.middleware.iter().find(|v| &v.name == name).cloned())
+                    .collect();
+            }
+        }
+        if let Some(options) = &apis.global_options {
+            let options = options.borrow();
+            doc.core_api_endpoints = core_api_endpoints(
+                &options.core_apis,
+                options.core_api_base.as_deref().unwrap_or(DEFAULT_CORE_API_BASE),
+                (&options.start_pos, &options.end_pos),
+            );
+        }
+        let endpoints = doc.rest.iter_mut().flat_map(|v| v.endpoints.iter_mut());
+        for pipeline in doc.pipelines.iter_mut().chain(endpoints.map(|v| &mut v.pipeline)) {
+            pipeline.apply_implicit_steps(&doc.implicit_steps);
+        }
+        //when no tables are listed, every table gets CRUD endpoints
+        let mut crud = vec![];
+        for db in &doc.databases {
+            for schema in &db.schemas {
+                for table in &schema.tables {
+                    let table_ref = TableRef {
+                        db: db.name.clone(),
+                        schema: schema.name.clone(),
+                        table: table.name.clone(),
+                    };
+                    if doc.crud_enabled_tables.is_empty()
+                        || doc.crud_enabled_tables.iter().any(|v| table_ref.matches(v))
+                    {
+                        crud.extend(crud_endpoints(table));
+                    }
+                }
+            }
+        }
+        doc.crud_endpoints = crud;
+        for endpoint in doc.crud_endpoints.iter_mut().chain(doc.core_api_endpoints.iter_mut()) {
+            endpoint.validation = doc.validation;
+            endpoint.limits = doc.request_limits;
+        }
+        doc.index = DocumentIndex::new(&doc);
         doc
     }
 }
@@ -110,6 +645,50 @@ pub struct GraphQLApiDef {
     pub base: String,
     pub from: String,
     pub enable_subscriptions: bool,
+    pub resolvers: Vec<ResolverDef>,
+    pub expose_as_subgraph: bool,
+    pub federation_keys: Vec<FederationKeyDef>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FederationKeyDef {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub table: String,
+    ///The columns that make up the key, more than one for a compound key
+    pub key: Vec<String>,
+}
+
+impl From<&ParsedFederationKey> for FederationKeyDef {
+    fn from(value: &ParsedFederationKey) -> Self {
+        FederationKeyDef {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            table: value.table.clone(),
+            key: value.key.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolverDef {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub typ: GraphQLOperation,
+    pub field: String,
+    pub pipeline: String,
+}
+
+impl From<&ParsedResolver> for ResolverDef {
+    fn from(value: &ParsedResolver) -> Self {
+        ResolverDef {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            typ: value.typ.clone(),
+            field: value.field.clone(),
+            pipeline: value.pipeline.clone(),
+        }
+    }
 }
 
 impl From<&ParsedGraphQL> for GraphQLApiDef {
@@ -120,6 +699,9 @@ impl From<&ParsedGraphQL> for GraphQLApiDef {
             base: value.base.clone(),
             from: value.from.clone(),
             enable_subscriptions: value.enable_subscriptions,
+            resolvers: value.resolvers.iter().map(|v| (&*v.borrow()).into()).collect(),
+            expose_as_subgraph: value.expose_as_subgraph,
+            federation_keys: value.federation_keys.iter().map(|v| (&*v.borrow()).into()).collect(),
         }
     }
 }
@@ -136,6 +718,8 @@ pub struct JobDef {
     pub interval_frequency: String,
     pub enabled: bool,
     pub repeats: bool,
+    ///The job is left out by [DocumentDef::with_features] unless this feature is enabled
+    pub feature: Option<String>,
 }
 
 impl From<&ParsedJob> for JobDef {
@@ -151,6 +735,7 @@ impl From<&ParsedJob> for JobDef {
             interval_frequency: value.interval_frequency.clone(),
             enabled: value.enabled,
             repeats: value.repeats,
+            feature: value.feature.clone(),
         }
     }
 }
@@ -160,20 +745,70 @@ pub struct RestApiDef {
     pub start_pos: Location,
     pub end_pos: Location,
     pub base: String,
+    ///Every endpoint, including those of the [versions](RestApiDef::versions) whose paths start with their version's
+    ///base
     pub endpoints: Vec<EndpointDef>,
+    pub versions: Vec<ApiVersionDef>,
 }
 
 impl From<&ParsedRest> for RestApiDef {
     fn from(value: &ParsedRest) -> Self {
+        let mut endpoints: Vec<EndpointDef> = value
+            .endpoints
+            .iter()
+            .map(|v| (&*v.borrow()).into())
+            .collect();
+        for version in value.versions.iter().map(|v| v.borrow()) {
+            let base = version.base.trim_matches('/');
+            endpoints.extend(version.endpoints.iter().map(|v| {
+                let mut endpoint: EndpointDef = (&*v.borrow()).into();
+                endpoint.path = Some(match endpoint.path.as_deref().map(|v| v.trim_matches('/')) {
+                    Some(path) if !path.is_empty() => format!("{}/{}", base, path),
+                    _ => base.to_owned(),
+                });
+                endpoint.version = Some(version.name.clone());
+                endpoint
+            }));
+        }
         RestApiDef {
             start_pos: value.start_pos.clone(),
             end_pos: value.end_pos.clone(),
             base: value.base.clone(),
-            endpoints: value
-                .endpoints
-                .iter()
-                .map(|v| (&*v.borrow()).into())
-                .collect(),
+            endpoints,
+            versions: value.versions.iter().map(|v| (&*v.borrow()).into()).collect(),
+        }
+    }
+}
+
+impl RestApiDef {
+    ///The version that deprecates `version`, if any
+    pub fn deprecated_by(&self, version: &str) -> Option<&ApiVersionDef> {
+        self.versions.iter().find(|v| v.deprecates.as_deref() == Some(version))
+    }
+}
+
+///A version of the REST API, its endpoints are in [RestApiDef::endpoints] with [EndpointDef::version] set
+#[derive(Clone, Debug)]
+pub struct ApiVersionDef {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub name: String,
+    pub base: String,
+    ///The name of the version this one replaces
+    pub deprecates: Option<String>,
+    ///The day the deprecated version stops being served, `YYYY-MM-DD`
+    pub sunset: Option<String>,
+}
+
+impl From<&ParsedApiVersion> for ApiVersionDef {
+    fn from(value: &ParsedApiVersion) -> Self {
+        ApiVersionDef {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            name: value.name.clone(),
+            base: value.base.clone(),
+            deprecates: value.deprecates.clone(),
+            sunset: value.sunset.clone(),
         }
     }
 }
@@ -186,11 +821,33 @@ pub struct EndpointDef {
     pub path: Option<String>,
     pub name: Option<String>,
     pub public: Option<bool>,
-    pub accepts: Option<String>,
-    pub produces: Option<String>,
+    pub accepts: Option<MediaType>,
+    pub produces: Option<MediaType>,
     ///The name of the pipeline which is executed when this endpoint is called
     pub pipeline: Pipeline,
+    ///The fields accepted in the request body, only known for generated endpoints
+    pub request: Vec<Mapping>,
     pub responses: Vec<ResponseDef>,
+    ///Error mappings for this endpoint only, these take precedence over [DocumentDef::error_mappings]
+    pub error_mappings: Vec<ErrorMappingDef>,
+    ///The endpoint's own validation or the global one if it doesn't set one
+    pub validation: ValidationMode,
+    ///Why the endpoint shouldn't be called any more and what to call instead
+    pub deprecated: Option<String>,
+    ///The middleware that run around the endpoint, in the order they run. Resolved from the names the endpoint
+    ///lists when the document is manifested
+    pub middleware: Vec<MiddlewareDef>,
+    pub cache: Option<CacheDef>,
+    ///The endpoint's own limits with the global ones filling in any it doesn't set
+    pub limits: RequestLimits,
+    pub log: LogPolicy,
+    ///The endpoint is left out by [DocumentDef::with_features] unless this feature is enabled
+    pub feature: Option<String>,
+    ///The name of the api-version the endpoint is in, see [RestApiDef::versions]
+    pub version: Option<String>,
+    ///The comments written just before the endpoint, see
+    ///[ParseOptions::comments_as_docs](crate::haml_parser::ParseOptions::comments_as_docs)
+    pub doc: Option<String>,
 }
 
 impl From<&ParsedEndpoint> for EndpointDef {
@@ -205,24 +862,371 @@ impl From<&ParsedEndpoint> for EndpointDef {
             accepts: value.accepts.clone(),
             produces: value.produces.clone(),
             pipeline: (&*value.pipeline.borrow()).into(),
+            request: vec![],
             responses: value
                 .responses
                 .iter()
                 .map(|v| (&*v.borrow()).into())
                 .collect(),
+            error_mappings: error_mappings(&value.errors),
+            validation: value.validation.unwrap_or_default(),
+            deprecated: value.deprecated.clone(),
+            middleware: vec![],
+            cache: value.cache.as_ref().map(|v| (&*v.borrow()).into()),
+            limits: value.limits,
+            log: value.log.clone(),
+            feature: value.feature.clone(),
+            version: None,
+            doc: value.doc.clone(),
         }
     }
 }
 
+///Tells the runtime it can cache the results of an endpoint or sql step
 #[derive(Clone, Debug)]
-pub struct ResponseDef {
+pub struct CacheDef {
+    pub start_pos: Location,
+    pub ttl: Duration,
+    ///The raw key and its parsed form, when not set endpoints are cached by their path and query and sql steps by
+    ///their bound parameters
+    pub key: Option<(String, MappingExpr)>,
+    ///The request headers whose values are part of the key, always empty for sql steps
+    pub vary: Vec<String>,
+}
+
+impl From<&ParsedCache> for CacheDef {
+    fn from(value: &ParsedCache) -> Self {
+        CacheDef {
+            start_pos: value.start_pos.clone(),
+            //validation requires a ttl
+            ttl: value.ttl.unwrap_or_default(),
+            key: value.key.clone(),
+            vary: value.vary.clone(),
+        }
+    }
+}
+
+///A feature flag declared under apis, see [DocumentDef::with_features]
+#[derive(Clone, Debug)]
+pub struct FeatureDef {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub name: String,
+    pub default: bool,
+}
+
+impl From<&ParsedFeature> for FeatureDef {
+    fn from(value: &ParsedFeature) -> Self {
+        FeatureDef {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            name: value.name.clone(),
+            default: value.default,
+        }
+    }
+}
+
+///A middleware declared under apis, see [EndpointDef::middleware]
+#[derive(Clone, Debug)]
+pub struct MiddlewareDef {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub name: String,
+    pub runs: Middleware,
+}
+
+#[derive(Clone, Debug)]
+pub enum Middleware {
+    ///The name of a pipeline declared under apis
+    Pipeline(String),
+    Step(DockerStep),
+}
+
+impl From<&ParsedMiddleware> for MiddlewareDef {
+    fn from(value: &ParsedMiddleware) -> Self {
+        MiddlewareDef {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            name: value.name.clone(),
+            //validation requires one or the other
+            runs: match &value.step {
+                Some(step) => Middleware::Step((&*step.borrow()).into()),
+                None => Middleware::Pipeline(value.pipeline.clone().unwrap_or_default()),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ErrorMappingDef {
     pub start_pos: Location,
     pub end_pos: Location,
+    pub code: String,
     pub status: u16,
+    pub template: Option<TemplateEngine>,
+    pub body: Option<String>,
+}
+
+impl From<&ParsedErrorMapping> for ErrorMappingDef {
+    fn from(value: &ParsedErrorMapping) -> Self {
+        ErrorMappingDef {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            code: value.code.clone(),
+            status: value.status,
+            template: value.template.clone(),
+            body: value.body.clone(),
+        }
+    }
+}
+
+fn error_mappings(errors: &Option<Rc<RefCell<ParsedErrors>>>) -> Vec<ErrorMappingDef> {
+    errors
+        .as_ref()
+        .map(|v| v.borrow().errors.iter().map(|v| (&*v.borrow()).into()).collect())
+        .unwrap_or_default()
+}
+
+fn http_method(name: &str) -> HttpMethod {
+    HttpMethod::from(&name.to_owned()).expect("a standard HTTP method")
+}
+
+///An endpoint that isn't declared in the document e.g. CRUD and core API endpoints.
+///The pipeline has the same name as the endpoint and no steps, the runtime provides the implementation.
+fn generated_endpoint(
+    loc: (&Location, &Location),
+    name: String,
+    method: &str,
+    path: String,
+    status: u16,
+    request: Vec<Mapping>,
+    response: Vec<Mapping>,
+) -> EndpointDef {
+    EndpointDef {
+        start_pos: loc.0.clone(),
+        end_pos: loc.1.clone(),
+        method: http_method(method),
+        path: Some(path),
+        name: Some(name.clone()),
+        public: None,
+        accepts: if request.is_empty() { None } else { Some(MediaType::json()) },
+        produces: if response.is_empty() { None } else { Some(MediaType::json()) },
+        pipeline: Pipeline {
+            start_pos: loc.0.clone(),
+            end_pos: loc.1.clone(),
+            name,
+            label: None,
+            steps: vec![],
+            input: None,
+            output: None,
+            skip_implicit_steps: false,
+            is_async: false,
+            deprecated: None,
+            feature: None,
+        },
+        request,
+        responses: vec![ResponseDef {
+            start_pos: loc.0.clone(),
+            end_pos: loc.1.clone(),
+            status: HttpStatus(status),
+            when: None,
+            condition: None,
+            is_default: true,
+            yield_expr: None,
+            yield_range: None,
+            body: None,
+            template: None,
+            mappings: response,
+        }],
+        error_mappings: vec![],
+        validation: ValidationMode::default(),
+        deprecated: None,
+        middleware: vec![],
+        cache: None,
+        limits: RequestLimits::default(),
+        log: LogPolicy::default(),
+        feature: None,
+        version: None,
+        doc: None,
+    }
+}
+
+fn field(name: &str, typ: ColumnType) -> Mapping {
+    Mapping {
+        start_pos: Location::default(),
+        end_pos: Location::default(),
+        from: name.to_owned(),
+        expr: Some(MappingExpr::Field(vec![PathSegment::Field(name.to_owned())])),
+        to: Some(name.to_owned()),
+        typ: Some(typ),
+        children: vec![],
+        readable_by: vec![],
+        writable_by: vec![],
+        transforms: vec![],
+    }
+}
+
+///[DocumentDef::resolve_table] against an index of tables, e.g. a copy kept while the document is changed
+pub(crate) fn resolve_table_in<'a>(index: &'a HashMap<String, TableRef>, name: &str) -> Option<&'a TableRef> {
+    let mut matches = index.values().filter(|v| v.matches(name));
+    match (matches.next(), matches.next()) {
+        (Some(table), None) => Some(table),
+        _ => None,
+    }
+}
+
+///Expands a table into its CRUD endpoints, paths are relative to the REST API's base.
+///```text
+///GET    /:table      - list rows
+///GET    /:table/:id  - get a row
+///POST   /:table      - insert a row
+///PUT    /:table/:id  - update a row
+///DELETE /:table/:id  - delete a row
+///```
+pub fn crud_endpoints(table: &TableDef) -> Vec<EndpointDef> {
+    let columns: Vec<Mapping> = table
+        .columns
+        .iter()
+        .map(|column| Mapping {
+            start_pos: column.start_pos.clone(),
+            end_pos: column.end_pos.clone(),
+            readable_by: column.readable_by.clone(),
+            writable_by: column.writable_by.clone(),
+            ..field(&column.name, column.typ.clone())
+        })
+        .collect();
+    let loc = (&table.start_pos, &table.end_pos);
+    let name = |action: &str| format!("{}_{}", action, table.name);
+    let collection = table.name.clone();
+    let item = format!("{}/:id", table.name);
+    vec![
+        generated_endpoint(loc, name("list"), "get", collection.clone(), 200, vec![], columns.clone()),
+        generated_endpoint(loc, name("get"), "get", item.clone(), 200, vec![], columns.clone()),
+        generated_endpoint(loc, name("create"), "post", collection, 201, columns.clone(), columns.clone()),
+        generated_endpoint(loc, name("update"), "put", item.clone(), 200, columns.clone(), columns),
+        generated_endpoint(loc, name("delete"), "delete", item, 204, vec![], vec![]),
+    ]
+}
+
+///The base path of the core API endpoints when `core-api-base` is not set on global-options
+pub const DEFAULT_CORE_API_BASE: &str = "auth";
+
+///Generates the endpoints of the given core APIs with paths relative to `base`,
+///which is itself relative to the REST API's base e.g. `auth/login/email`.
+pub fn core_api_endpoints(apis: &[CoreApi], base: &str, loc: (&Location, &Location)) -> Vec<EndpointDef> {
+    let base = base.trim_matches('/');
+    let path = |path: &str| if base.is_empty() { path.to_owned() } else { format!("{}/{}", base, path) };
+    let session = || vec![field("token", ColumnType::TEXT), field("expires", ColumnType::TIMESTAMP)];
+    apis.iter()
+        .map(|api| {
+            let (name, method, endpoint_path, status, request, response) = match api {
+                CoreApi::Register => (
+                    "register",
+                    "post",
+                    "register",
+                    201,
+                    vec![
+                        field("username", ColumnType::TEXT),
+                        field("email", ColumnType::TEXT),
+                        field("password", ColumnType::TEXT),
+                    ],
+                    vec![
+                        field("id", ColumnType::TEXT),
+                        field("username", ColumnType::TEXT),
+                        field("email", ColumnType::TEXT),
+                    ],
+                ),
+                CoreApi::LoginByEmail => (
+                    "login_by_email",
+                    "post",
+                    "login/email",
+                    200,
+                    vec![field("email", ColumnType::TEXT), field("password", ColumnType::TEXT)],
+                    session(),
+                ),
+                CoreApi::LoginByUsername => (
+                    "login_by_username",
+                    "post",
+                    "login/username",
+                    200,
+                    vec![field("username", ColumnType::TEXT), field("password", ColumnType::TEXT)],
+                    session(),
+                ),
+                //redirects to the provider
+                CoreApi::OAuth => ("oauth", "get", "oauth/:provider", 302, vec![], vec![]),
+                CoreApi::PasswordResetTrigger => (
+                    "password_reset_trigger",
+                    "post",
+                    "password-reset/trigger",
+                    202,
+                    vec![field("email", ColumnType::TEXT)],
+                    vec![],
+                ),
+                CoreApi::PasswordReset => (
+                    "password_reset",
+                    "post",
+                    "password-reset",
+                    200,
+                    vec![field("code", ColumnType::TEXT), field("password", ColumnType::TEXT)],
+                    vec![],
+                ),
+                CoreApi::VerifyAccount => (
+                    "verify_account",
+                    "post",
+                    "verify-account",
+                    200,
+                    vec![field("code", ColumnType::TEXT)],
+                    vec![],
+                ),
+                CoreApi::MagicLink => (
+                    "magic_link",
+                    "post",
+                    "magic-link",
+                    202,
+                    vec![field("email", ColumnType::TEXT)],
+                    vec![],
+                ),
+                CoreApi::TwoFactorAuthEmail => ("2fa_email", "post", "2fa/email", 202, vec![], vec![]),
+                CoreApi::TwoFactorAuthSms => ("2fa_sms", "post", "2fa/sms", 202, vec![], vec![]),
+                CoreApi::TwoFactorStep2 => (
+                    "2fa_step2",
+                    "post",
+                    "2fa/step2",
+                    200,
+                    vec![field("code", ColumnType::TEXT)],
+                    session(),
+                ),
+                CoreApi::TwoFactorTotp => (
+                    "2fa_totp",
+                    "post",
+                    "2fa/totp",
+                    200,
+                    vec![field("code", ColumnType::TEXT)],
+                    session(),
+                ),
+            };
+            generated_endpoint(loc, name.to_owned(), method, path(endpoint_path), status, request, response)
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct ResponseDef {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub status: HttpStatus,
     pub when: Option<String>,
+    ///The parsed form of `when`, ready to evaluate
+    pub condition: Option<Condition>,
+    ///The response used when none of the endpoint's when conditions match. A response without a when condition is
+    ///the default whether it's marked `default="true"` or not
+    pub is_default: bool,
     pub yield_expr: Option<String>,
+    ///The parsed form of `yield_expr`
+    pub yield_range: Option<YieldRange>,
     ///A response body template
     pub body: Option<String>,
+    pub template: Option<TemplateEngine>,
     pub mappings: Vec<Mapping>,
 }
 
@@ -231,10 +1235,15 @@ impl From<&ParsedEndpointResponse> for ResponseDef {
         ResponseDef {
             start_pos: value.start_pos.clone(),
             end_pos: value.end_pos.clone(),
-            status: value.status,
+            //validation ensures every response has a status
+            status: value.status.unwrap_or(HttpStatus(200)),
             when: value.when.clone(),
+            condition: value.condition.clone(),
+            is_default: value.default || value.when.is_none(),
             yield_expr: value.yield_expr.clone(),
+            yield_range: value.yield_range,
             body: value.body.clone(),
+            template: value.template.clone(),
             mappings: value
                 .mappings
                 .iter()
@@ -252,6 +1261,10 @@ pub struct TableDef {
     pub columns: Vec<ColumnDef>,
     pub constraints: Vec<ConstraintDef>,
     pub hypi: Option<HypiDef>,
+    pub search_indexes: Vec<SearchIndexDef>,
+    ///The comments written just before the table, see
+    ///[ParseOptions::comments_as_docs](crate::haml_parser::ParseOptions::comments_as_docs)
+    pub doc: Option<String>,
 }
 
 impl From<&ParsedTable> for TableDef {
@@ -269,6 +1282,50 @@ impl From<&ParsedTable> for TableDef {
                 .map(|v| (&*v.borrow()).into())
                 .collect(),
             hypi: value.hypi.as_ref().map(|v| (&*v.borrow()).into()),
+            search_indexes: value
+                .search_indexes
+                .iter()
+                .map(|v| (&*v.borrow()).into())
+                .collect(),
+            doc: value.doc.clone(),
+        }
+    }
+}
+
+impl TableDef {
+    fn normalize(&mut self) {
+        self.name = self.name.to_lowercase();
+        for column in &mut self.columns {
+            column.name = column.name.to_lowercase();
+        }
+        for constraint in &mut self.constraints {
+            constraint.name = constraint.name.to_lowercase();
+            lowercase_all(&mut constraint.columns);
+        }
+        for index in &mut self.search_indexes {
+            index.name = index.name.to_lowercase();
+            lowercase_all(&mut index.columns);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchIndexDef {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub name: String,
+    pub columns: Vec<String>,
+    pub language: Option<String>,
+}
+
+impl From<&ParsedSearchIndex> for SearchIndexDef {
+    fn from(value: &ParsedSearchIndex) -> Self {
+        SearchIndexDef {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            name: value.name.clone(),
+            columns: value.columns.clone(),
+            language: value.language.clone(),
         }
     }
 }
@@ -284,10 +1341,35 @@ pub struct ColumnDef {
     pub default: Option<ColumnDefault>,
     pub primary_key: bool,
     pub pipeline: Option<ColumnPipeline>,
+    ///The roles allowed to read the column, not restricted if empty
+    pub readable_by: Vec<String>,
+    ///The roles allowed to write the column, not restricted if empty
+    pub writable_by: Vec<String>,
+    ///None if the column has no validation rules
+    pub validation: Option<ColumnValidation>,
+    pub sensitive: bool,
+    ///The mask applied to a sensitive column, [MaskPolicy::Redact] unless the column sets one
+    pub mask: Option<MaskPolicy>,
+    pub encryption: Option<ColumnEncryption>,
+    ///Why the column shouldn't be used any more and what to use instead
+    pub deprecated: Option<String>,
+    ///The comments written just before the column, see
+    ///[ParseOptions::comments_as_docs](crate::haml_parser::ParseOptions::comments_as_docs)
+    pub doc: Option<String>,
 }
 
 impl From<&ParsedColumn> for ColumnDef {
     fn from(value: &ParsedColumn) -> Self {
+        let sensitive = value.sensitive || value.mask.is_some();
+        let mask = if sensitive { Some(value.mask.clone().unwrap_or_default()) } else { None };
+        let mut pipeline: Option<ColumnPipeline> = value.pipeline.as_ref().map(|v| (&*v.borrow()).into());
+        //sensitive columns are masked on read unless the column's pipeline says otherwise
+        if let Some(mask) = &mask {
+            let pipeline = pipeline.get_or_insert_with(ColumnPipeline::default);
+            if pipeline.read_start_pos.is_none() {
+                pipeline.read = vec![mask.read_function().to_owned()];
+            }
+        }
         ColumnDef {
             start_pos: value.start_pos.clone(),
             end_pos: value.end_pos.clone(),
@@ -297,7 +1379,19 @@ impl From<&ParsedColumn> for ColumnDef {
             unique: value.unique,
             default: value.default.clone(),
             primary_key: value.primary_key,
-            pipeline: value.pipeline.as_ref().map(|v| (&*v.borrow()).into()),
+            pipeline,
+            readable_by: value.readable_by.clone(),
+            writable_by: value.writable_by.clone(),
+            validation: if value.validation == ColumnValidation::default() {
+                None
+            } else {
+                Some(value.validation.clone())
+            },
+            sensitive,
+            mask,
+            encryption: value.encryption(),
+            deprecated: value.deprecated.clone(),
+            doc: value.doc.clone(),
         }
     }
 }
@@ -328,7 +1422,7 @@ impl From<&ParsedConstraint> for ConstraintDef {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct ColumnPipeline {
     pub args_start_pos: Option<Location>,
     pub args_end_pos: Option<Location>,
@@ -375,7 +1469,7 @@ impl From<&ParsedColumnPipeline> for ColumnPipeline {
                 .clone()
                 .unwrap_or_else(|| vec![]),
             write: value
-                .args
+                .write
                 .as_ref()
                 .map(|v| {
                     (&*v.borrow())
@@ -387,7 +1481,7 @@ impl From<&ParsedColumnPipeline> for ColumnPipeline {
                 .clone()
                 .unwrap_or_else(|| vec![]),
             read: value
-                .args
+                .read
                 .as_ref()
                 .map(|v| {
                     (&*v.borrow())
@@ -430,9 +1524,13 @@ pub struct Mapping {
     pub start_pos: Location,
     pub end_pos: Location,
     pub from: String,
+    pub expr: Option<MappingExpr>,
     pub to: Option<String>,
     pub typ: Option<ColumnType>,
     pub children: Vec<Mapping>,
+    pub readable_by: Vec<String>,
+    pub writable_by: Vec<String>,
+    pub transforms: Vec<MappingTransform>,
 }
 
 impl From<&ParsedMapping> for Mapping {
@@ -441,6 +1539,7 @@ impl From<&ParsedMapping> for Mapping {
             start_pos: value.start_pos.clone(),
             end_pos: value.end_pos.clone(),
             from: value.from.clone(),
+            expr: value.expr.clone(),
             to: value.to.clone(),
             typ: value.typ.clone(),
             children: value
@@ -448,6 +1547,9 @@ impl From<&ParsedMapping> for Mapping {
                 .iter()
                 .map(|v| (&*v.borrow()).into())
                 .collect(),
+            readable_by: value.readable_by.clone(),
+            writable_by: value.writable_by.clone(),
+            transforms: value.transforms.clone(),
         }
     }
 }
@@ -457,8 +1559,18 @@ pub struct Pipeline {
     pub end_pos: Location,
     pub name: String,
     pub label: Option<String>,
-    pub steps: Vec<DockerStep>,
+    pub steps: Vec<Step>,
+    ///The fields the pipeline takes, anything goes if it doesn't declare an input
+    pub input: Option<Vec<PipelineField>>,
+    ///The fields the pipeline gives back to whatever calls it
+    pub output: Option<Vec<PipelineField>>,
+    ///The [implicit steps](DocumentDef::implicit_steps) aren't placed in the pipeline if true
+    pub skip_implicit_steps: bool,
     pub is_async: bool,
+    ///Why the pipeline shouldn't be used any more and what to use instead
+    pub deprecated: Option<String>,
+    ///The pipeline is left out by [DocumentDef::with_features] unless this feature is enabled
+    pub feature: Option<String>,
 }
 
 impl From<&ParsedPipeline> for Pipeline {
@@ -468,13 +1580,399 @@ impl From<&ParsedPipeline> for Pipeline {
             end_pos: value.end_pos.clone(),
             name: value.name.to_owned(),
             label: value.label.to_owned(),
+            skip_implicit_steps: value.skip_implicit_steps,
             is_async: value.is_async,
-            steps: value
-                .steps
-                .borrow()
-                .iter()
-                .map(|v| (&*v.borrow()).into())
-                .collect(),
+            deprecated: value.deprecated.clone(),
+            feature: value.feature.clone(),
+            steps: value.steps.borrow().iter().map(|v| v.into()).collect(),
+            input: value
+                .input
+                .as_ref()
+                .map(|v| v.borrow().fields.iter().map(|v| (&*v.borrow()).into()).collect()),
+            output: value
+                .output
+                .as_ref()
+                .map(|v| v.borrow().fields.iter().map(|v| (&*v.borrow()).into()).collect()),
+        }
+    }
+}
+
+impl Pipeline {
+    ///The steps the runtime executes when this pipeline runs, in order. Call steps that run another pipeline, or an
+    ///endpoint's, are replaced by that pipeline's steps, unless it is already running further up the calls. Each
+    ///pipeline's steps include the [implicit steps](DocumentDef::implicit_steps) placed in it.
+    pub fn execution_plan(&self, doc: &DocumentDef) -> Vec<PlannedStep> {
+        self.inlined_steps(doc, &mut vec![self.name.as_str()])
+    }
+
+    ///How many pipelines deep this pipeline goes through its call steps, 1 if it calls none. Pipelines already
+    ///running further up the calls aren't followed again
+    pub fn call_depth(&self, doc: &DocumentDef) -> usize {
+        self.nested_depth(doc, &mut vec![self.name.as_str()])
+    }
+
+    ///Inserts `implicit` steps where their placement puts them, unless the pipeline skips them. Steps placed at the
+    ///same position run in the order they're given
+    pub(crate) fn apply_implicit_steps(&mut self, implicit: &[DockerStep]) {
+        if self.skip_implicit_steps {
+            return;
+        }
+        let names: Vec<&str> = self.steps.iter().map(|v| v.name()).collect();
+        let mut inserted: Vec<(usize, &DockerStep)> = vec![];
+        for step in implicit {
+            inserted.extend(step.placement.insertion_indices(&names).into_iter().map(|i| (i, step)));
+        }
+        //stable, so steps at the same index keep their order
+        inserted.sort_by_key(|(i, _)| *i);
+        for (offset, (i, step)) in inserted.into_iter().enumerate() {
+            self.steps.insert(i + offset, Step::Docker(DockerStep { implicit: true, ..step.clone() }));
+        }
+    }
+
+    fn nested_depth<'a>(&'a self, doc: &'a DocumentDef, running: &mut Vec<&'a str>) -> usize {
+        let mut deepest = 0;
+        for step in &self.steps {
+            if let Step::Call(call) = step {
+                let called = doc.call_target_pipeline(&call.target).filter(|v| !running.contains(&v.name.as_str()));
+                if let Some(pipeline) = called {
+                    running.push(&pipeline.name);
+                    deepest = deepest.max(pipeline.nested_depth(doc, running));
+                    running.pop();
+                }
+            }
+        }
+        deepest + 1
+    }
+
+    fn inlined_steps<'a>(&'a self, doc: &'a DocumentDef, running: &mut Vec<&'a str>) -> Vec<PlannedStep> {
+        let mut steps = vec![];
+        for step in &self.steps {
+            let called = match step {
+                Step::Call(call) => doc
+                    .call_target_pipeline(&call.target)
+                    .filter(|v| !running.contains(&v.name.as_str())),
+                _ => None,
+            };
+            match called {
+                Some(pipeline) => {
+                    running.push(&pipeline.name);
+                    steps.extend(pipeline.inlined_steps(doc, running).into_iter().map(|mut v| {
+                        v.pipelines.insert(0, self.name.clone());
+                        v
+                    }));
+                    running.pop();
+                }
+                None => steps.push(PlannedStep {
+                    step: step.clone(),
+                    pipelines: vec![self.name.clone()],
+                    implicit: matches!(step, Step::Docker(step) if step.implicit),
+                }),
+            }
+        }
+        steps
+    }
+}
+
+///A step of a pipeline's [execution plan](Pipeline::execution_plan)
+#[derive(Clone, Debug)]
+pub struct PlannedStep {
+    pub step: Step,
+    ///The pipeline the step is declared in, last, and the pipelines that called it to get there
+    pub pipelines: Vec<String>,
+    ///True for the implicit steps of global-options
+    pub implicit: bool,
+}
+
+///A field of a pipeline's input or output
+#[derive(Clone, Debug)]
+pub struct PipelineField {
+    pub start_pos: Location,
+    pub name: String,
+    ///Values of any type are accepted if not set
+    pub typ: Option<ColumnType>,
+    pub required: bool,
+}
+
+impl From<&ParsedPipelineField> for PipelineField {
+    fn from(value: &ParsedPipelineField) -> Self {
+        PipelineField {
+            start_pos: value.start_pos.clone(),
+            name: value.name.clone(),
+            typ: value.typ.clone(),
+            required: value.required,
+        }
+    }
+}
+
+///A step of a pipeline, in the order it runs
+#[derive(Clone, Debug)]
+pub enum Step {
+    Docker(DockerStep),
+    Sql(SqlStep),
+    Script(ScriptStep),
+    Fn(FnStep),
+    Call(CallStep),
+}
+
+impl Step {
+    pub fn name(&self) -> &str {
+        match self {
+            Step::Docker(step) => &step.name,
+            Step::Sql(step) => &step.name,
+            Step::Script(step) => &step.name,
+            Step::Fn(step) => &step.name,
+            Step::Call(step) => &step.name,
+        }
+    }
+
+    pub fn start_pos(&self) -> &Location {
+        match self {
+            Step::Docker(step) => &step.start_pos,
+            Step::Sql(step) => &step.start_pos,
+            Step::Script(step) => &step.start_pos,
+            Step::Fn(step) => &step.start_pos,
+            Step::Call(step) => &step.start_pos,
+        }
+    }
+
+    pub fn end_pos(&self) -> &Location {
+        match self {
+            Step::Docker(step) => &step.end_pos,
+            Step::Sql(step) => &step.end_pos,
+            Step::Script(step) => &step.end_pos,
+            Step::Fn(step) => &step.end_pos,
+            Step::Call(step) => &step.end_pos,
+        }
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        match self {
+            Step::Docker(step) => &step.mappings,
+            Step::Sql(step) => &step.mappings,
+            Step::Script(step) => &step.mappings,
+            Step::Fn(step) => &step.mappings,
+            Step::Call(step) => &step.mappings,
+        }
+    }
+
+    pub fn log(&self) -> &LogPolicy {
+        match self {
+            Step::Docker(step) => &step.log,
+            Step::Sql(step) => &step.log,
+            Step::Script(step) => &step.log,
+            Step::Fn(step) => &step.log,
+            Step::Call(step) => &step.log,
+        }
+    }
+
+    ///A short name for the kind of step, sql or the [kind](DockerStepProvider::kind) of a docker step's provider
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Step::Docker(step) => step.provider.kind(),
+            Step::Sql(_) => "sql",
+            Step::Script(_) => "script",
+            Step::Fn(_) => "fn",
+            Step::Call(_) => "call",
+        }
+    }
+
+    pub(crate) fn mappings_mut(&mut self) -> &mut Vec<Mapping> {
+        match self {
+            Step::Docker(step) => &mut step.mappings,
+            Step::Sql(step) => &mut step.mappings,
+            Step::Script(step) => &mut step.mappings,
+            Step::Fn(step) => &mut step.mappings,
+            Step::Call(step) => &mut step.mappings,
+        }
+    }
+
+    fn name_mut(&mut self) -> &mut String {
+        match self {
+            Step::Docker(step) => &mut step.name,
+            Step::Sql(step) => &mut step.name,
+            Step::Script(step) => &mut step.name,
+            Step::Fn(step) => &mut step.name,
+            Step::Call(step) => &mut step.name,
+        }
+    }
+}
+
+impl From<&ParsedStep> for Step {
+    fn from(value: &ParsedStep) -> Self {
+        match value {
+            ParsedStep::Docker(step) => Step::Docker((&*step.borrow()).into()),
+            ParsedStep::Sql(step) => Step::Sql((&*step.borrow()).into()),
+            ParsedStep::Script(step) => Step::Script((&*step.borrow()).into()),
+            ParsedStep::Fn(step) => Step::Fn((&*step.borrow()).into()),
+            ParsedStep::Call(step) => Step::Call((&*step.borrow()).into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SqlStep {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub name: String,
+    pub db: Option<String>,
+    pub sql: String,
+    ///The file `sql` was imported from
+    pub import: Option<String>,
+    ///The `:named` parameters of `sql`, each is bound by the mapping whose `to` is its name
+    pub parameters: Vec<String>,
+    pub mappings: Vec<Mapping>,
+    pub cache: Option<CacheDef>,
+    pub log: LogPolicy,
+}
+
+impl From<&ParsedSqlStep> for SqlStep {
+    fn from(value: &ParsedSqlStep) -> Self {
+        SqlStep {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            name: value.name.clone(),
+            db: value.db.clone(),
+            sql: value.sql.clone(),
+            import: value.import.clone(),
+            parameters: value.parameters.clone(),
+            mappings: value.mappings.iter().map(|v| (&*v.borrow()).into()).collect(),
+            cache: value.cache_ttl.map(|ttl| CacheDef {
+                start_pos: value.start_pos.clone(),
+                ttl,
+                key: value.cache_key.clone(),
+                vary: vec![],
+            }),
+            log: value.log.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScriptStep {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub name: String,
+    pub lang: ScriptType,
+    pub source: String,
+    ///The file `source` was imported from
+    pub import: Option<String>,
+    pub limits: ScriptLimits,
+    pub mappings: Vec<Mapping>,
+    pub log: LogPolicy,
+}
+
+impl From<&ParsedScriptStep> for ScriptStep {
+    fn from(value: &ParsedScriptStep) -> Self {
+        ScriptStep {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            name: value.name.clone(),
+            //validation sets the language
+            lang: value.lang.unwrap_or_default(),
+            source: value.source.clone(),
+            import: value.import.clone(),
+            limits: value.limits.clone(),
+            mappings: value.mappings.iter().map(|v| (&*v.borrow()).into()).collect(),
+            log: value.log.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FnStep {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    ///The name of the function
+    pub name: String,
+    pub version: Option<String>,
+    pub mappings: Vec<Mapping>,
+    pub log: LogPolicy,
+}
+
+impl From<&ParsedFnStep> for FnStep {
+    fn from(value: &ParsedFnStep) -> Self {
+        FnStep {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            name: value.name.clone(),
+            version: value.version.clone(),
+            mappings: value.mappings.iter().map(|v| (&*v.borrow()).into()).collect(),
+            log: value.log.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CallStep {
+    pub start_pos: Location,
+    pub end_pos: Location,
+    pub name: String,
+    pub target: CallTarget,
+    pub mappings: Vec<Mapping>,
+    pub log: LogPolicy,
+}
+
+impl From<&ParsedCallStep> for CallStep {
+    fn from(value: &ParsedCallStep) -> Self {
+        CallStep {
+            start_pos: value.start_pos.clone(),
+            end_pos: value.end_pos.clone(),
+            name: value.name.clone(),
+            //validate fails call steps without a target
+            target: value.target.clone().unwrap_or(CallTarget::Pipeline(String::new())),
+            mappings: value.mappings.iter().map(|v| (&*v.borrow()).into()).collect(),
+            log: value.log.clone(),
+        }
+    }
+}
+
+///What a call step calls, parsed from its `target` attribute
+#[derive(Clone, Debug)]
+pub enum CallTarget {
+    ///`endpoint.<name>.<method>`, the endpoint with that [name](EndpointDef::name) and method
+    Endpoint { name: String, method: HttpMethod },
+    ///`pipeline.<name>`
+    Pipeline(String),
+    ///`core-api.<name>` e.g. `core-api.login-by-email`
+    CoreApi(CoreApi),
+}
+
+impl FromStr for CallTarget {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid call target '{}'. Call endpoint.<name>.<method>, pipeline.<name> OR core-api.<name>",
+                input
+            )
+        };
+        let (kind, rest) = input.trim().split_once('.').ok_or_else(invalid)?;
+        match kind {
+            "endpoint" => {
+                let (name, method) = rest.rsplit_once('.').ok_or_else(invalid)?;
+                let method = HttpMethod::from(&method.to_owned())
+                    .ok_or_else(|| format!("Invalid call target '{}'. '{}' isn't an HTTP method", input, method))?;
+                if name.is_empty() {
+                    return Err(invalid());
+                }
+                Ok(CallTarget::Endpoint { name: name.to_owned(), method })
+            }
+            "pipeline" if !rest.is_empty() => Ok(CallTarget::Pipeline(rest.to_owned())),
+            "core-api" => Ok(CallTarget::CoreApi(rest.parse()?)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for CallTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallTarget::Endpoint { name, method } => {
+                write!(f, "endpoint.{}.{}", name, format!("{:?}", method).to_lowercase())
+            }
+            CallTarget::Pipeline(name) => write!(f, "pipeline.{}", name),
+            CallTarget::CoreApi(api) => write!(f, "core-api.{}", api),
         }
     }
 }
@@ -486,8 +1984,16 @@ pub struct DockerStep {
     pub name: String,
     pub provider: DockerStepProvider,
     pub mappings: Vec<Mapping>,
-    pub implicit_before_position: Option<ImplicitDockerStepPosition>,
-    pub implicit_after_position: Option<ImplicitDockerStepPosition>,
+    pub placement: StepPlacement,
+    pub env: Vec<EnvVar>,
+    pub ports: Vec<DockerPort>,
+    pub volumes: Vec<DockerVolume>,
+    pub resources: Option<DockerResources>,
+    pub pull_policy: Option<PullPolicy>,
+    pub healthcheck: Option<DockerHealthcheck>,
+    ///True if the step is one of the [implicit steps](DocumentDef::implicit_steps) placed in the pipeline
+    pub implicit: bool,
+    pub log: LogPolicy,
 }
 
 impl From<&ParsedDockerStep> for DockerStep {
@@ -497,8 +2003,15 @@ impl From<&ParsedDockerStep> for DockerStep {
             end_pos: value.end_pos.clone(),
             name: value.name.to_owned(),
             provider: value.provider.to_owned(),
-            implicit_before_position: value.implicit_before_position.clone(),
-            implicit_after_position: value.implicit_after_position.clone(),
+            placement: value.placement.clone(),
+            env: value.env.borrow().iter().map(|v| (&*v.borrow()).into()).collect(),
+            ports: value.ports.borrow().iter().map(|v| v.borrow().clone()).collect(),
+            volumes: value.volumes.borrow().iter().map(|v| v.borrow().clone()).collect(),
+            resources: value.resources.as_ref().map(|v| v.borrow().clone()),
+            pull_policy: value.pull_policy.clone(),
+            healthcheck: value.healthcheck.as_ref().map(|v| v.borrow().clone()),
+            implicit: false,
+            log: value.log.clone(),
             mappings: value
                 .mappings
                 .borrow()
@@ -538,6 +2051,15 @@ pub struct DatabaseDef {
     pub db_name: String,
     pub host: String,
     pub port: Option<u16>,
+    ///The database file for file based databases like sqlite
+    pub file: Option<String>,
+    pub pool: PoolConfig,
+    ///Only set when the db element configures TLS
+    pub tls: Option<DbTlsConfig>,
+    pub read_preference: ReadPreference,
+    pub replicas: Vec<DbReplica>,
+    ///The connection string the connection details were read from, if the db element used one
+    pub url: Option<String>,
     pub schemas: Vec<SchemaDef>,
 }
 
@@ -553,6 +2075,12 @@ impl From<&ParsedDb> for DatabaseDef {
             db_name: value.db_name.to_owned(),
             host: value.host.to_owned(),
             port: value.port.to_owned(),
+            file: value.file.to_owned(),
+            pool: value.pool.clone(),
+            tls: if value.tls == DbTlsConfig::default() { None } else { Some(value.tls.clone()) },
+            read_preference: value.read_preference.clone(),
+            replicas: value.replicas.borrow().iter().map(|v| v.borrow().clone()).collect(),
+            url: value.url.clone(),
             schemas: (&*value.schemas.borrow())
                 .iter()
                 .map(|v| (&*v.borrow()).into())
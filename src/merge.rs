@@ -0,0 +1,393 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::manifested_schema::{
+    crud_endpoints, endpoint_key, ApiVersionDef, DatabaseDef, DocumentDef, EndpointDef, EnvVar, ErrorMappingDef,
+    FeatureDef, JobDef, MiddlewareDef, Pipeline, TableDef,
+};
+use crate::Location;
+
+///What [DocumentDef::merge] does when two documents declare the same thing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MergePolicy {
+    ///Fail with a [MergeConflict]
+    #[default]
+    Error,
+    ///Keep what the document merged last declares
+    LastWins,
+    ///Rename conflicting tables, pipelines and jobs to `<file>_<name>` and move conflicting endpoints to
+    ///`<file>/<path>`, where file is the name of the document's file without its extension. Other conflicts fail
+    NamespacePrefix,
+}
+
+///Two of the documents passed to [DocumentDef::merge] declare the same thing
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    ///What conflicts e.g. table or pipeline
+    pub kind: &'static str,
+    pub name: String,
+    pub first: Location,
+    pub second: Location,
+}
+
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The {} '{}' declared at {} is declared again at {}",
+            self.kind, self.name, self.first, self.second
+        )
+    }
+}
+
+impl DocumentDef {
+    ///Combines this document with `others`, in order, into one document e.g. to deploy schemas maintained by
+    ///different teams as one app. Databases with the same label are combined, as are their schemas with the same
+    ///name. Tables, pipelines, jobs, endpoints, env vars and error mappings declared by more than one document are
+    ///resolved by `policy`. Settings a document declares once, like the message providers, storage and the GraphQL
+    ///API, come from the first document that sets them unless the policy is [MergePolicy::LastWins].
+    pub fn merge(&self, others: &[DocumentDef], policy: MergePolicy) -> Result<DocumentDef, MergeConflict> {
+        let mut merged = self.clone();
+        for other in others {
+            let mut other = other.clone();
+            if policy == MergePolicy::NamespacePrefix {
+                namespace(&merged, &mut other);
+            }
+            merge_into(&mut merged, other, policy)?;
+        }
+        merged.reindex();
+        Ok(merged)
+    }
+}
+
+///Something that conflicts if two documents declare it with the same key
+trait Declared {
+    const KIND: &'static str;
+    fn key(&self) -> String;
+    fn pos(&self) -> &Location;
+}
+
+impl Declared for TableDef {
+    const KIND: &'static str = "table";
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+impl Declared for Pipeline {
+    const KIND: &'static str = "pipeline";
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+impl Declared for JobDef {
+    const KIND: &'static str = "job";
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+impl Declared for ApiVersionDef {
+    const KIND: &'static str = "api-version";
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+impl Declared for FeatureDef {
+    const KIND: &'static str = "feature";
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+impl Declared for MiddlewareDef {
+    const KIND: &'static str = "middleware";
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+impl Declared for EndpointDef {
+    const KIND: &'static str = "endpoint";
+    fn key(&self) -> String {
+        endpoint_key(&self.method, self.path.as_deref().unwrap_or(""))
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+impl Declared for EnvVar {
+    const KIND: &'static str = "env var";
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+impl Declared for ErrorMappingDef {
+    const KIND: &'static str = "error mapping";
+    fn key(&self) -> String {
+        self.code.clone()
+    }
+    fn pos(&self) -> &Location {
+        &self.start_pos
+    }
+}
+
+fn merge_declared<T: Declared>(existing: &mut Vec<T>, incoming: Vec<T>, policy: MergePolicy) -> Result<(), MergeConflict> {
+    for item in incoming {
+        match existing.iter().position(|v| v.key() == item.key()) {
+            None => existing.push(item),
+            Some(i) if policy == MergePolicy::LastWins => existing[i] = item,
+            Some(i) => {
+                return Err(MergeConflict {
+                    kind: T::KIND,
+                    name: item.key(),
+                    first: existing[i].pos().clone(),
+                    second: item.pos().clone(),
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+///Takes `incoming` if there's no `existing` setting or the last document wins
+fn merge_setting<T>(existing: &mut Option<T>, incoming: Option<T>, policy: MergePolicy) {
+    if incoming.is_some() && (existing.is_none() || policy == MergePolicy::LastWins) {
+        *existing = incoming;
+    }
+}
+
+fn merge_into(merged: &mut DocumentDef, other: DocumentDef, policy: MergePolicy) -> Result<(), MergeConflict> {
+    for db in other.databases {
+        match merged.databases.iter_mut().find(|v| v.name == db.name) {
+            Some(existing) => merge_database(existing, db, policy)?,
+            None => merged.databases.push(db),
+        }
+    }
+    match (&mut merged.rest, other.rest) {
+        (Some(rest), Some(incoming)) => {
+            if rest.base != incoming.base {
+                if policy != MergePolicy::LastWins {
+                    return Err(MergeConflict {
+                        kind: "rest base",
+                        name: incoming.base,
+                        first: rest.start_pos.clone(),
+                        second: incoming.start_pos,
+                    });
+                }
+                rest.base = incoming.base;
+            }
+            merge_declared(&mut rest.endpoints, incoming.endpoints, policy)?;
+            merge_declared(&mut rest.versions, incoming.versions, policy)?;
+        }
+        (rest, incoming) => merge_setting(rest, incoming, policy),
+    }
+    merge_declared(&mut merged.crud_endpoints, other.crud_endpoints, policy)?;
+    //core API endpoints are generated the same way by every document that enables them
+    for endpoint in other.core_api_endpoints {
+        if !merged.core_api_endpoints.iter().any(|v| v.key() == endpoint.key()) {
+            merged.core_api_endpoints.push(endpoint);
+        }
+    }
+    merge_declared(&mut merged.pipelines, other.pipelines, policy)?;
+    merge_declared(&mut merged.jobs, other.jobs, policy)?;
+    merge_declared(&mut merged.middleware, other.middleware, policy)?;
+    merge_declared(&mut merged.features, other.features, policy)?;
+    let env = other
+        .env
+        .into_iter()
+        .filter(|v| !merged.env.iter().any(|e| e.name == v.name && e.value == v.value))
+        .collect();
+    merge_declared(&mut merged.env, env, policy)?;
+    merge_declared(&mut merged.error_mappings, other.error_mappings, policy)?;
+    for table in other.crud_enabled_tables {
+        if !merged.crud_enabled_tables.contains(&table) {
+            merged.crud_enabled_tables.push(table);
+        }
+    }
+    for api in other.enabled_core_apis {
+        if !merged.enabled_core_apis.contains(&api) {
+            merged.enabled_core_apis.push(api);
+        }
+    }
+    for (api, options) in other.core_api_options {
+        merged.core_api_options.entry(api).or_default().extend(options);
+    }
+    merged.step_builders.extend(other.step_builders);
+    merged.implicit_steps.extend(other.implicit_steps);
+    merged.meta.pairs.extend(other.meta.pairs);
+    merge_setting(&mut merged.sms_provider, other.sms_provider, policy);
+    merge_setting(&mut merged.email_provider, other.email_provider, policy);
+    merge_setting(&mut merged.totp, other.totp, policy);
+    merge_setting(&mut merged.lockout_policy, other.lockout_policy, policy);
+    merge_setting(&mut merged.graphql, other.graphql, policy);
+    merge_setting(&mut merged.storage, other.storage, policy);
+    merge_setting(&mut merged.observability, other.observability, policy);
+    merge_setting(&mut merged.haml_version, other.haml_version, policy);
+    Ok(())
+}
+
+///Databases with the same label must connect to the same database, unless the last document wins
+fn merge_database(existing: &mut DatabaseDef, mut incoming: DatabaseDef, policy: MergePolicy) -> Result<(), MergeConflict> {
+    let connection = |v: &DatabaseDef| {
+        format!("{:?}", (&v.typ, &v.host, v.port, &v.db_name, &v.file, &v.url, &v.username, &v.password))
+    };
+    let schemas = std::mem::take(&mut incoming.schemas);
+    if connection(existing) != connection(&incoming) {
+        if policy != MergePolicy::LastWins {
+            return Err(MergeConflict {
+                kind: "database",
+                name: incoming.name,
+                first: existing.start_pos.clone(),
+                second: incoming.start_pos,
+            });
+        }
+        let existing_schemas = std::mem::take(&mut existing.schemas);
+        *existing = DatabaseDef {
+            schemas: existing_schemas,
+            ..incoming
+        };
+    }
+    for schema in schemas {
+        match existing.schemas.iter_mut().find(|v| v.name == schema.name) {
+            Some(existing) => merge_declared(&mut existing.tables, schema.tables, policy)?,
+            None => existing.schemas.push(schema),
+        }
+    }
+    Ok(())
+}
+
+///Renames what `other` declares that `merged` already has, see [MergePolicy::NamespacePrefix]
+fn namespace(merged: &DocumentDef, other: &mut DocumentDef) {
+    let prefix = Path::new(&other.start_pos.file_name)
+        .file_stem()
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let rename = |name: &str| format!("{}_{}", prefix, name);
+    let mut renamed_tables = vec![];
+    for db in &mut other.databases {
+        let existing_schemas = merged
+            .databases
+            .iter()
+            .filter(|v| v.name == db.name)
+            .flat_map(|v| v.schemas.iter());
+        for existing in existing_schemas {
+            for schema in db.schemas.iter_mut().filter(|v| v.name == existing.name) {
+                for table in &mut schema.tables {
+                    if existing.tables.iter().any(|v| v.name == table.name) {
+                        renamed_tables.push(table.name.clone());
+                        table.name = rename(&table.name);
+                    }
+                }
+            }
+        }
+    }
+    for table in &renamed_tables {
+        rename_table_references(other, table, &rename(table));
+    }
+    for pipeline in &mut other.pipelines {
+        if merged.pipelines.iter().any(|v| v.name == pipeline.name) {
+            let name = rename(&pipeline.name);
+            for job in other.jobs.iter_mut().filter(|v| v.pipeline == pipeline.name) {
+                job.pipeline = name.clone();
+            }
+            let resolvers = other.graphql.iter_mut().flat_map(|v| v.resolvers.iter_mut());
+            for resolver in resolvers.filter(|v| v.pipeline == pipeline.name) {
+                resolver.pipeline = name.clone();
+            }
+            pipeline.name = name;
+        }
+    }
+    for job in &mut other.jobs {
+        if merged.jobs.iter().any(|v| v.name == job.name) {
+            job.name = rename(&job.name);
+        }
+    }
+    let endpoints = other
+        .rest
+        .iter_mut()
+        .flat_map(|v| v.endpoints.iter_mut())
+        .chain(other.crud_endpoints.iter_mut());
+    for endpoint in endpoints {
+        if merged.endpoints().any(|v| v.key() == endpoint.key()) {
+            let path = endpoint.path.as_deref().unwrap_or("").trim_start_matches('/');
+            endpoint.path = Some(format!("{}/{}", prefix, path));
+        }
+    }
+}
+
+///Points the foreign keys, CRUD settings and CRUD endpoints of `doc` at a table's new name
+fn rename_table_references(doc: &mut DocumentDef, from: &str, to: &str) {
+    //references are `table.column`, optionally qualified by the schema and db
+    let rename = |reference: &mut String| {
+        let mut parts: Vec<&str> = reference.split('.').collect();
+        let table = parts.len().saturating_sub(2);
+        if parts.len() > 1 && parts[table] == from {
+            parts[table] = to;
+            *reference = parts.join(".");
+        }
+    };
+    let tables = doc
+        .databases
+        .iter_mut()
+        .flat_map(|v| v.schemas.iter_mut())
+        .flat_map(|v| v.tables.iter_mut());
+    for table in tables {
+        let mappings = table.constraints.iter_mut().flat_map(|v| v.mappings.iter_mut());
+        mappings.filter_map(|v| v.to.as_mut()).for_each(rename);
+    }
+    for table in &mut doc.crud_enabled_tables {
+        if table.rsplit('.').next() == Some(from) {
+            let qualifier = &table[..table.len() - from.len()];
+            *table = format!("{}{}", qualifier, to);
+        }
+    }
+    replace_crud_endpoints(doc, from, to);
+}
+
+///Replaces the CRUD endpoints generated for the table `from` with those of the table now called `to`, if any were
+///generated
+pub(crate) fn replace_crud_endpoints(doc: &mut DocumentDef, from: &str, to: &str) {
+    let mut crud: Vec<EndpointDef> = doc
+        .databases
+        .iter()
+        .flat_map(|v| v.schemas.iter())
+        .flat_map(|v| v.tables.iter())
+        .filter(|v| v.name == to)
+        .flat_map(crud_endpoints)
+        .collect();
+    let generated_for = |v: &EndpointDef| v.path.as_deref().unwrap_or("").split('/').next() == Some(from);
+    if doc.crud_endpoints.iter().any(generated_for) {
+        doc.crud_endpoints.retain(|v| !generated_for(v));
+        for endpoint in &mut crud {
+            endpoint.validation = doc.validation;
+            endpoint.limits = doc.request_limits;
+        }
+        doc.crud_endpoints.extend(crud);
+    }
+}
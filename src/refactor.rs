@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::diagnostic::Range;
+use crate::manifested_schema::{
+    resolve_table_in, CallTarget, DocumentDef, Mapping, Middleware, Pipeline, Step, TableDef,
+};
+use crate::merge::replace_crud_endpoints;
+use crate::symbols::range;
+use crate::{sql, Location, TableConstraintType, TableRef};
+
+///A change a refactoring made to one attribute, or the text, of an element. Making the same changes to the files
+///the document was parsed from brings them in line with the refactored document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub file: String,
+    ///The range of the element that changes, see [Symbol::range](crate::symbols::Symbol::range). The global
+    ///options aren't kept in the document so a change to the tables CRUD is enabled on has the document's range
+    pub range: Range,
+    pub element: &'static str,
+    ///None when the element's text changes e.g. the statement of a sql step
+    pub attribute: Option<&'static str>,
+    ///The whole value before and after the change, e.g. every column of a constraint's `columns`
+    pub old: String,
+    pub new: String,
+}
+
+impl Edit {
+    fn new(
+        start: &Location,
+        end: &Location,
+        element: &'static str,
+        attribute: Option<&'static str>,
+        old: String,
+        new: String,
+    ) -> Self {
+        Edit {
+            file: start.file_name.clone(),
+            range: range(start, end),
+            element,
+            attribute,
+            old,
+            new,
+        }
+    }
+
+    ///Sets `value` to `new` and records the change
+    fn change(
+        value: &mut String,
+        new: &str,
+        start: &Location,
+        end: &Location,
+        element: &'static str,
+        attribute: Option<&'static str>,
+    ) -> Self {
+        let old = std::mem::replace(value, new.to_owned());
+        Edit::new(start, end, element, attribute, old, new.to_owned())
+    }
+
+    ///Replaces `from` with `to` in a comma separated attribute, recording the change if `from` is in it
+    fn change_list(
+        values: &mut [String],
+        from: &str,
+        to: &str,
+        start: &Location,
+        end: &Location,
+        element: &'static str,
+        attribute: &'static str,
+    ) -> Option<Self> {
+        if !values.iter().any(|v| v == from) {
+            return None;
+        }
+        let old = values.join(",");
+        values.iter_mut().filter(|v| *v == from).for_each(|v| *v = to.to_owned());
+        Some(Edit::new(start, end, element, Some(attribute), old, values.join(",")))
+    }
+}
+
+///Why a rename couldn't be done, the document is left as it was
+#[derive(Debug, Clone)]
+pub enum RenameError {
+    ///Nothing of the kind has the name. For a table also when the name matches more than one table
+    NotFound { kind: &'static str, name: String },
+    ///Something of the kind already has the new name
+    Conflict {
+        kind: &'static str,
+        name: String,
+        declared: Location,
+    },
+}
+
+impl Display for RenameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameError::NotFound { kind, name } => write!(f, "There's no {} called '{}'", kind, name),
+            RenameError::Conflict { kind, name, declared } => {
+                write!(f, "The {} '{}' is already declared at {}", kind, name, declared)
+            }
+        }
+    }
+}
+
+impl DocumentDef {
+    ///Renames a table and updates what refers to it: the `table.column` references of foreign keys and of the
+    ///mappings of pipelines and endpoints, the tables CRUD is enabled on, GraphQL federation keys and the
+    ///generated CRUD endpoints. `table` can take any of the forms [DocumentDef::resolve_table] does.
+    ///Returns the changes made, ordered by file and position.
+    pub fn rename_table(&mut self, table: &str, new_name: &str) -> Result<Vec<Edit>, RenameError> {
+        let target = self.resolve_table(table).cloned().ok_or_else(|| RenameError::NotFound {
+            kind: "table",
+            name: table.to_owned(),
+        })?;
+        let renamed = format!("{}.{}.{}", target.db, target.schema, new_name);
+        if let Some(existing) = self.find_table(&renamed) {
+            return Err(RenameError::Conflict {
+                kind: "table",
+                name: renamed,
+                declared: existing.start_pos.clone(),
+            });
+        }
+        let index = self.table_index.clone();
+        let mut edits = vec![];
+        if let Some(def) = table_mut(self, &target) {
+            edits.push(Edit::change(&mut def.name, new_name, &def.start_pos, &def.end_pos, "table", Some("name")));
+        }
+        edits.extend(rewrite_references(self, &|near, reference| {
+            let (table, column) = reference.trim().rsplit_once('.')?;
+            if !refers_to(&index, near, table, &target) {
+                return None;
+            }
+            let qualifier = table.rsplit_once('.').map_or(String::new(), |(v, _)| format!("{}.", v));
+            Some(format!("{}{}.{}", qualifier, new_name, column))
+        }));
+        let old = self.crud_enabled_tables.join(",");
+        for name in &mut self.crud_enabled_tables {
+            if refers_to(&index, None, name, &target) {
+                let qualifier = name.rsplit_once('.').map_or(String::new(), |(v, _)| format!("{}.", v));
+                *name = format!("{}{}", qualifier, new_name);
+            }
+        }
+        let new = self.crud_enabled_tables.join(",");
+        if old != new {
+            let attribute = Some("enable-crud-on-tables");
+            edits.push(Edit::new(&self.start_pos, &self.end_pos, "global-options", attribute, old, new));
+        }
+        let keys = self.graphql.iter_mut().flat_map(|v| v.federation_keys.iter_mut());
+        for key in keys.filter(|v| refers_to(&index, None, &v.table, &target)) {
+            let qualifier = key.table.rsplit_once('.').map_or(String::new(), |(v, _)| format!("{}.", v));
+            let name = format!("{}{}", qualifier, new_name);
+            edits.push(Edit::change(&mut key.table, &name, &key.start_pos, &key.end_pos, "federation", Some("table")));
+        }
+        replace_crud_endpoints(self, &target.table, new_name);
+        self.reindex();
+        Ok(sorted(edits))
+    }
+
+    ///Renames a column of `table` and updates what refers to it: the constraints and search indexes of its table,
+    ///`table.column` references in foreign keys and the mappings of pipelines and endpoints, GraphQL federation
+    ///keys and the generated CRUD endpoints. With `sql_parameters` the `:column` parameters of the sql steps that
+    ///run against the table's db are renamed too, along with the mappings that bind them.
+    ///Returns the changes made, ordered by file and position.
+    pub fn rename_column(
+        &mut self,
+        table: &str,
+        column: &str,
+        new_name: &str,
+        sql_parameters: bool,
+    ) -> Result<Vec<Edit>, RenameError> {
+        let not_found = || RenameError::NotFound {
+            kind: "column",
+            name: format!("{}.{}", table, column),
+        };
+        let target = self.resolve_table(table).cloned().ok_or_else(not_found)?;
+        let index = self.table_index.clone();
+        let mut edits = vec![];
+        let def = table_mut(self, &target).ok_or_else(not_found)?;
+        if !def.columns.iter().any(|v| v.name == column) {
+            return Err(not_found());
+        }
+        if let Some(existing) = def.columns.iter().find(|v| v.name == new_name) {
+            return Err(RenameError::Conflict {
+                kind: "column",
+                name: format!("{}.{}", target.qualified_name(), new_name),
+                declared: existing.start_pos.clone(),
+            });
+        }
+        let col = def.columns.iter_mut().find(|v| v.name == column).ok_or_else(not_found)?;
+        edits.push(Edit::change(&mut col.name, new_name, &col.start_pos, &col.end_pos, "column", Some("name")));
+        for constraint in &mut def.constraints {
+            let (start, end) = (&constraint.start_pos, &constraint.end_pos);
+            edits.extend(Edit::change_list(
+                &mut constraint.columns,
+                column,
+                new_name,
+                start,
+                end,
+                "constraint",
+                "columns",
+            ));
+            if matches!(constraint.typ, TableConstraintType::ForeignKey { .. }) {
+                //the from of a foreign key's mapping is a column of its own table
+                for mapping in constraint.mappings.iter_mut().filter(|v| v.from == column) {
+                    let (start, end) = (&mapping.start_pos, &mapping.end_pos);
+                    edits.push(Edit::change(&mut mapping.from, new_name, start, end, "mapping", Some("from")));
+                }
+            }
+        }
+        for search in &mut def.search_indexes {
+            let (start, end) = (&search.start_pos, &search.end_pos);
+            edits.extend(Edit::change_list(&mut search.columns, column, new_name, start, end, "search", "columns"));
+        }
+        edits.extend(rewrite_references(self, &|near, reference| {
+            let (table, referenced) = reference.trim().rsplit_once('.')?;
+            if referenced != column || !refers_to(&index, near, table, &target) {
+                return None;
+            }
+            Some(format!("{}.{}", table, new_name))
+        }));
+        let keys = self.graphql.iter_mut().flat_map(|v| v.federation_keys.iter_mut());
+        for key in keys.filter(|v| refers_to(&index, None, &v.table, &target)) {
+            let (start, end) = (&key.start_pos, &key.end_pos);
+            edits.extend(Edit::change_list(&mut key.key, column, new_name, start, end, "federation", "key"));
+        }
+        if sql_parameters {
+            let steps = pipelines_mut(self).flat_map(|v| v.steps.iter_mut());
+            for step in steps {
+                let Step::Sql(step) = step else { continue };
+                let in_db = step.db.is_none() || step.db.as_ref() == Some(&target.db);
+                if step.import.is_some() || !in_db || !step.parameters.iter().any(|v| v == column) {
+                    continue;
+                }
+                let sql = sql::rename_parameter(&step.sql, column, new_name);
+                edits.push(Edit::change(&mut step.sql, &sql, &step.start_pos, &step.end_pos, "sql", None));
+                step.parameters = sql::parameters(&step.sql);
+                for mapping in step.mappings.iter_mut() {
+                    if let Some(to) = mapping.to.as_mut().filter(|v| *v == column) {
+                        let (start, end) = (&mapping.start_pos, &mapping.end_pos);
+                        edits.push(Edit::change(to, new_name, start, end, "mapping", Some("to")));
+                    }
+                }
+            }
+        }
+        replace_crud_endpoints(self, &target.table, &target.table);
+        self.reindex();
+        Ok(sorted(edits))
+    }
+
+    ///Renames a pipeline declared under apis or by an endpoint and updates what refers to it: jobs, GraphQL
+    ///resolvers, middleware and call steps. Returns the changes made, ordered by file and position.
+    pub fn rename_pipeline(&mut self, name: &str, new_name: &str) -> Result<Vec<Edit>, RenameError> {
+        if self.find_pipeline(name).is_none() {
+            return Err(RenameError::NotFound {
+                kind: "pipeline",
+                name: name.to_owned(),
+            });
+        }
+        if let Some(existing) = self.find_pipeline(new_name) {
+            return Err(RenameError::Conflict {
+                kind: "pipeline",
+                name: new_name.to_owned(),
+                declared: existing.start_pos.clone(),
+            });
+        }
+        let mut edits = vec![];
+        for pipeline in pipelines_mut(self) {
+            if pipeline.name == name {
+                let (start, end) = (&pipeline.start_pos, &pipeline.end_pos);
+                edits.push(Edit::change(&mut pipeline.name, new_name, start, end, "pipeline", Some("name")));
+            }
+            for step in &mut pipeline.steps {
+                match step {
+                    Step::Call(call) if matches!(&call.target, CallTarget::Pipeline(v) if v == name) => {
+                        call.target = CallTarget::Pipeline(new_name.to_owned());
+                        let (old, new) = (format!("pipeline.{}", name), format!("pipeline.{}", new_name));
+                        edits.push(Edit::new(&call.start_pos, &call.end_pos, "call", Some("target"), old, new));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for job in self.jobs.iter_mut().filter(|v| v.pipeline == name) {
+            let (start, end) = (&job.start_pos, &job.end_pos);
+            edits.push(Edit::change(&mut job.pipeline, new_name, start, end, "job", Some("pipeline")));
+        }
+        let resolvers = self.graphql.iter_mut().flat_map(|v| v.resolvers.iter_mut());
+        for resolver in resolvers.filter(|v| v.pipeline == name) {
+            let (start, end) = (&resolver.start_pos, &resolver.end_pos);
+            edits.push(Edit::change(&mut resolver.pipeline, new_name, start, end, "resolver", Some("pipeline")));
+        }
+        for middleware in &mut self.middleware {
+            if let Middleware::Pipeline(pipeline) = &mut middleware.runs {
+                if pipeline == name {
+                    let (start, end) = (&middleware.start_pos, &middleware.end_pos);
+                    edits.push(Edit::change(pipeline, new_name, start, end, "middleware", Some("pipeline")));
+                }
+            }
+        }
+        //endpoints keep copies of the middleware they run, the edits are already made for the declarations
+        let endpoints = self.rest.iter_mut().flat_map(|v| v.endpoints.iter_mut());
+        for middleware in endpoints.flat_map(|v| v.middleware.iter_mut()) {
+            if let Middleware::Pipeline(pipeline) = &mut middleware.runs {
+                if pipeline == name {
+                    *pipeline = new_name.to_owned();
+                }
+            }
+        }
+        self.reindex();
+        Ok(sorted(edits))
+    }
+}
+
+fn table_mut<'a>(doc: &'a mut DocumentDef, table: &TableRef) -> Option<&'a mut TableDef> {
+    doc.databases
+        .iter_mut()
+        .filter(|v| v.name == table.db)
+        .flat_map(|v| v.schemas.iter_mut())
+        .filter(|v| v.name == table.schema)
+        .flat_map(|v| v.tables.iter_mut())
+        .find(|v| v.name == table.table)
+}
+
+///The pipelines declared under apis and those of declared endpoints, generated endpoints aren't in any file
+fn pipelines_mut(doc: &mut DocumentDef) -> impl Iterator<Item=&mut Pipeline> {
+    let endpoints = doc.rest.iter_mut().flat_map(|v| v.endpoints.iter_mut());
+    doc.pipelines.iter_mut().chain(endpoints.map(|v| &mut v.pipeline))
+}
+
+///True if `name` is `target` in a reference from `near`, where an unqualified name is first looked up in the
+///schema of `near`, the same way [crate::graph] resolves foreign keys. `index` is a copy of the table index
+///from before the rename
+fn refers_to(index: &HashMap<String, TableRef>, near: Option<&TableRef>, name: &str, target: &TableRef) -> bool {
+    near.and_then(|v| resolve_table_in(index, &format!("{}.{}.{}", v.db, v.schema, name)))
+        .or_else(|| resolve_table_in(index, name))
+        == Some(target)
+}
+
+///Rewrites the `from` and `to` of every mapping of foreign keys, pipelines and declared endpoints that `rewrite`
+///returns a new value for. `rewrite` is given the table a foreign key is declared in
+fn rewrite_references(doc: &mut DocumentDef, rewrite: &dyn Fn(Option<&TableRef>, &str) -> Option<String>) -> Vec<Edit> {
+    let mut edits = vec![];
+    for db in &mut doc.databases {
+        for schema in &mut db.schemas {
+            for table in &mut schema.tables {
+                let near = TableRef {
+                    db: db.name.clone(),
+                    schema: schema.name.clone(),
+                    table: table.name.clone(),
+                };
+                for constraint in &mut table.constraints {
+                    rewrite_mappings(&mut constraint.mappings, Some(&near), rewrite, &mut edits);
+                }
+            }
+        }
+    }
+    for endpoint in doc.rest.iter_mut().flat_map(|v| v.endpoints.iter_mut()) {
+        rewrite_mappings(&mut endpoint.request, None, rewrite, &mut edits);
+        for response in &mut endpoint.responses {
+            rewrite_mappings(&mut response.mappings, None, rewrite, &mut edits);
+        }
+    }
+    for step in pipelines_mut(doc).flat_map(|v| v.steps.iter_mut()) {
+        rewrite_mappings(step.mappings_mut(), None, rewrite, &mut edits);
+    }
+    edits
+}
+
+fn rewrite_mappings(
+    mappings: &mut [Mapping],
+    near: Option<&TableRef>,
+    rewrite: &dyn Fn(Option<&TableRef>, &str) -> Option<String>,
+    edits: &mut Vec<Edit>,
+) {
+    for mapping in mappings {
+        let (start, end) = (&mapping.start_pos, &mapping.end_pos);
+        if let Some(from) = rewrite(near, &mapping.from) {
+            edits.push(Edit::change(&mut mapping.from, &from, start, end, "mapping", Some("from")));
+        }
+        if let Some(to) = mapping.to.as_mut() {
+            if let Some(new) = rewrite(near, to) {
+                edits.push(Edit::change(to, &new, start, end, "mapping", Some("to")));
+            }
+        }
+        rewrite_mappings(&mut mapping.children, near, rewrite, edits);
+    }
+}
+
+fn sorted(mut edits: Vec<Edit>) -> Vec<Edit> {
+    edits.sort_by(|a, b| {
+        let key = |v: &Edit| (v.range.start.line, v.range.start.column);
+        a.file.cmp(&b.file).then(key(a).cmp(&key(b)))
+    });
+    edits
+}
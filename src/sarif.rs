@@ -0,0 +1,99 @@
+use serde_json::{json, Value};
+
+use crate::diagnostic::{HamlDiagnostic, Range};
+use crate::haml_parser::{find_diagnostic, Severity};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+
+///Render diagnostics as a SARIF 2.1.0 log with a single run, the format GitHub code scanning and GitLab take to
+///annotate files in a pull request. Each code that's reported becomes a rule, described from the
+///[diagnostic catalog](crate::haml_parser::diagnostic_catalog) when it's in there.
+///Diagnostics without a file (i.e. semantic errors) are reported without a location.
+pub fn to_sarif(diagnostics: &[HamlDiagnostic]) -> Value {
+    let mut rule_ids: Vec<&str> = vec![];
+    for diagnostic in diagnostics {
+        if !rule_ids.contains(&diagnostic.code.as_str()) {
+            rule_ids.push(diagnostic.code.as_str());
+        }
+    }
+    let rules: Vec<Value> = rule_ids
+        .iter()
+        .map(|id| match find_diagnostic(id) {
+            Some(info) => json!({
+                "id": id,
+                "shortDescription": {"text": info.message},
+                "helpUri": info.docs_url(),
+                "defaultConfiguration": {"level": level(info.severity)},
+            }),
+            None => json!({"id": id}),
+        })
+        .collect();
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut result = json!({
+                "ruleId": diagnostic.code,
+                "ruleIndex": rule_ids.iter().position(|id| *id == diagnostic.code).unwrap_or_default(),
+                "level": level(diagnostic.severity),
+                "message": {"text": diagnostic.message},
+            });
+            if !diagnostic.file.is_empty() {
+                result["locations"] = json!([location(&diagnostic.file, &diagnostic.range, None)]);
+            }
+            if !diagnostic.related.is_empty() {
+                let related: Vec<Value> = diagnostic
+                    .related
+                    .iter()
+                    .enumerate()
+                    .map(|(i, r)| {
+                        let mut loc = location(&r.file, &r.range, Some(r.message.as_str()));
+                        loc["id"] = json!(i);
+                        loc
+                    })
+                    .collect();
+                result["relatedLocations"] = Value::Array(related);
+            }
+            result
+        })
+        .collect();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    }
+}
+
+///SARIF lines and columns are 1 based like ours but a region can't start on line 0 so it's left out for those
+fn location(file: &str, range: &Range, message: Option<&str>) -> Value {
+    let mut physical = json!({"artifactLocation": {"uri": file}});
+    if range.start.line > 0 {
+        let mut region = json!({"startLine": range.start.line});
+        if range.start.column > 0 {
+            region["startColumn"] = json!(range.start.column);
+        }
+        physical["region"] = region;
+    }
+    let mut loc = json!({"physicalLocation": physical});
+    if let Some(message) = message {
+        loc["message"] = json!({"text": message});
+    }
+    loc
+}
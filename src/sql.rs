@@ -0,0 +1,78 @@
+///The `:named` parameters of a SQL statement in the order they first appear, each listed once. Colons in string
+///literals, quoted identifiers and comments are skipped, as are Postgres `::type` casts and slices like `a[1:2]`.
+pub fn parameters(sql: &str) -> Vec<String> {
+    let mut parameters: Vec<String> = vec![];
+    for span in parameter_spans(sql) {
+        let name = &sql[span];
+        if !parameters.iter().any(|v| v == name) {
+            parameters.push(name.to_owned());
+        }
+    }
+    parameters
+}
+
+///Renames the `:named` parameter `from` to `to` everywhere it's used in `sql`, skipping the same places
+///[parameters] does so a colon in a string literal or comment is left as it is
+pub fn rename_parameter(sql: &str, from: &str, to: &str) -> String {
+    let mut renamed = sql.to_owned();
+    for span in parameter_spans(sql).into_iter().rev().filter(|v| &sql[v.clone()] == from) {
+        renamed.replace_range(span, to);
+    }
+    renamed
+}
+
+///Where the name of each `:named` parameter is in `sql`, in order, without the colon
+fn parameter_spans(sql: &str) -> Vec<std::ops::Range<usize>> {
+    let mut spans = vec![];
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                chars.find(|(_, next)| *next == c);
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                chars.find(|(_, next)| *next == '\n');
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                chars.next();
+                let mut previous = ' ';
+                chars.find(|(_, next)| std::mem::replace(&mut previous, *next) == '*' && *next == '/');
+            }
+            ':' if matches!(chars.peek(), Some((_, ':'))) => {
+                chars.next();
+            }
+            ':' => {
+                let start = i + 1;
+                let mut end = start;
+                while let Some(&(j, next)) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_') || (j == start && next.is_ascii_digit()) {
+                        break;
+                    }
+                    end = j + next.len_utf8();
+                    chars.next();
+                }
+                if end > start {
+                    spans.push(start..end);
+                }
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+///Checks `sql` parses in the dialect of `typ`. Databases without a dialect of their own are checked as ANSI SQL
+#[cfg(feature = "sql-validation")]
+pub fn check_syntax(sql: &str, typ: &crate::DatabaseType) -> Result<(), String> {
+    use crate::DatabaseType;
+    use sqlparser::dialect::{Dialect, GenericDialect, MsSqlDialect, MySqlDialect, PostgreSqlDialect, SQLiteDialect};
+    use sqlparser::parser::Parser;
+    let dialect: Box<dyn Dialect> = match typ {
+        DatabaseType::Postgres | DatabaseType::CockroachDB => Box::new(PostgreSqlDialect {}),
+        DatabaseType::MySQL | DatabaseType::MariaDB => Box::new(MySqlDialect {}),
+        DatabaseType::MsSql => Box::new(MsSqlDialect {}),
+        DatabaseType::Sqlite => Box::new(SQLiteDialect {}),
+        DatabaseType::MekaDb | DatabaseType::Oracle => Box::new(GenericDialect {}),
+    };
+    Parser::parse_sql(dialect.as_ref(), sql).map(|_| ()).map_err(|e| e.to_string())
+}
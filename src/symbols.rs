@@ -0,0 +1,312 @@
+use crate::diagnostic::{Position, Range};
+use crate::manifested_schema::{
+    endpoint_key, DocumentDef, EndpointDef, ErrorMappingDef, Mapping, Middleware, Pipeline, Step,
+};
+use crate::Location;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Database,
+    Table,
+    Column,
+    Endpoint,
+    Pipeline,
+    Job,
+    EnvVar,
+}
+
+///A named entity declared in a document and where it's declared, the building block for outline views and
+///go-to-definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    ///The name as declared e.g. `id` for a column. Endpoints are named by their upper case method and path like
+    ///`GET account/{id}`
+    pub name: String,
+    pub file: String,
+    ///From the start of the element's opening tag to the start of its closing tag, for a self-closing element both
+    ///are the start of the element
+    pub range: Range,
+    ///The index in [DocumentDef::symbols] of the symbol this one is declared in e.g. a column's table, a table's
+    ///database
+    pub container: Option<usize>,
+}
+
+pub(crate) fn range(start: &Location, end: &Location) -> Range {
+    Range {
+        start: Position { line: start.line, column: start.column },
+        end: Position { line: end.line, column: end.column },
+    }
+}
+
+///All of the last line counts since element ranges end where the closing tag starts, i.e. anywhere in
+///`<column name="id"/>` is in the column
+fn in_range(range: &Range, line: u64, column: u64) -> bool {
+    (range.start.line, range.start.column) <= (line, column) && line <= range.end.line
+}
+
+impl Symbol {
+    fn new(kind: SymbolKind, name: String, start: &Location, end: &Location, container: Option<usize>) -> Self {
+        Symbol { kind, name, file: start.file_name.clone(), range: range(start, end), container }
+    }
+
+    ///The position is inside the symbol's range, all of its last line included
+    pub fn contains(&self, file: &str, line: u64, column: u64) -> bool {
+        self.file == file && in_range(&self.range, line, column)
+    }
+}
+
+impl DocumentDef {
+    ///Every database, table, column, env var, pipeline, endpoint and job declared in the document. A symbol always
+    ///comes after its container. Generated endpoints aren't declared anywhere so they aren't included.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols = vec![];
+        for db in &self.databases {
+            let db_index = symbols.len();
+            symbols.push(Symbol::new(SymbolKind::Database, db.name.clone(), &db.start_pos, &db.end_pos, None));
+            for table in db.schemas.iter().flat_map(|v| &v.tables) {
+                let table_index = symbols.len();
+                symbols.push(Symbol::new(
+                    SymbolKind::Table,
+                    table.name.clone(),
+                    &table.start_pos,
+                    &table.end_pos,
+                    Some(db_index),
+                ));
+                for column in &table.columns {
+                    symbols.push(Symbol::new(
+                        SymbolKind::Column,
+                        column.name.clone(),
+                        &column.start_pos,
+                        &column.end_pos,
+                        Some(table_index),
+                    ));
+                }
+            }
+        }
+        for env in &self.env {
+            symbols.push(Symbol::new(SymbolKind::EnvVar, env.name.clone(), &env.start_pos, &env.end_pos, None));
+        }
+        for pipeline in &self.pipelines {
+            symbols.push(Symbol::new(
+                SymbolKind::Pipeline,
+                pipeline.name.clone(),
+                &pipeline.start_pos,
+                &pipeline.end_pos,
+                None,
+            ));
+        }
+        for endpoint in self.rest.iter().flat_map(|v| &v.endpoints) {
+            let key = endpoint_key(&endpoint.method, endpoint.path.as_deref().unwrap_or(""));
+            symbols.push(Symbol::new(
+                SymbolKind::Endpoint,
+                key.trim_end().to_owned(),
+                &endpoint.start_pos,
+                &endpoint.end_pos,
+                None,
+            ));
+        }
+        for job in &self.jobs {
+            symbols.push(Symbol::new(SymbolKind::Job, job.name.clone(), &job.start_pos, &job.end_pos, None));
+        }
+        symbols
+    }
+
+    ///The innermost symbol declared at the position, e.g. the column rather than its table
+    pub fn symbol_at(&self, file: &str, line: u64, column: u64) -> Option<Symbol> {
+        let symbols = self.symbols();
+        let mut found: Option<usize> = None;
+        for (i, symbol) in symbols.iter().enumerate() {
+            //containers come first so a later match is nested in an earlier one
+            if symbol.contains(file, line, column) {
+                found = Some(i);
+            }
+        }
+        found.map(|i| symbols[i].clone())
+    }
+}
+
+///An element of a document and where it is, see [DocumentDef::node_at]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    ///The element's tag e.g. `column`, so the path can be looked up in the
+    ///[grammar](crate::haml_parser::find_element)
+    pub element: &'static str,
+    ///The element's name, key or code if it has one
+    pub name: Option<String>,
+    pub file: String,
+    pub range: Range,
+}
+
+struct NodeTree {
+    node: Node,
+    children: Vec<NodeTree>,
+}
+
+impl NodeTree {
+    fn new(element: &'static str, name: Option<&str>, start: &Location, end: &Location) -> Self {
+        NodeTree {
+            node: Node {
+                element,
+                name: name.map(|v| v.to_owned()),
+                file: start.file_name.clone(),
+                range: range(start, end),
+            },
+            children: vec![],
+        }
+    }
+
+    fn with(mut self, children: impl IntoIterator<Item=NodeTree>) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    fn contains(&self, file: &str, line: u64, column: u64) -> bool {
+        self.node.file == file && in_range(&self.node.range, line, column)
+    }
+
+    ///Descends into the last child containing the position, siblings don't overlap so it's the one that starts
+    ///closest before it. Children in another file than the position, like an endpoint's pipeline, are searched
+    ///through without being added to the path.
+    fn find(&self, file: &str, line: u64, column: u64, path: &mut Vec<Node>) -> bool {
+        if let Some(child) = self.children.iter().rev().find(|v| v.contains(file, line, column)) {
+            path.push(child.node.clone());
+            child.find(file, line, column, path);
+            return true;
+        }
+        self.children.iter().filter(|v| v.node.file != file).any(|v| v.find(file, line, column, path))
+    }
+}
+
+fn mapping_nodes(mappings: &[Mapping]) -> Vec<NodeTree> {
+    mappings
+        .iter()
+        .map(|v| {
+            NodeTree::new("mapping", v.to.as_deref().or(Some(v.from.as_str())), &v.start_pos, &v.end_pos)
+                .with(mapping_nodes(&v.children))
+        })
+        .collect()
+}
+
+fn step_node(step: &Step) -> NodeTree {
+    let element = match step {
+        Step::Docker(_) => "step",
+        Step::Sql(_) => "sql",
+        Step::Script(_) => "script",
+        Step::Fn(_) => "fn",
+        Step::Call(_) => "call",
+    };
+    NodeTree::new(element, Some(step.name()), step.start_pos(), step.end_pos()).with(mapping_nodes(step.mappings()))
+}
+
+fn pipeline_node(pipeline: &Pipeline) -> NodeTree {
+    NodeTree::new("pipeline", Some(&pipeline.name), &pipeline.start_pos, &pipeline.end_pos)
+        .with(pipeline.steps.iter().map(step_node))
+}
+
+fn error_nodes(errors: &[ErrorMappingDef]) -> Vec<NodeTree> {
+    errors.iter().map(|v| NodeTree::new("error", Some(&v.code), &v.start_pos, &v.end_pos)).collect()
+}
+
+fn endpoint_node(endpoint: &EndpointDef) -> NodeTree {
+    let mut node = NodeTree::new("endpoint", endpoint.name.as_deref(), &endpoint.start_pos, &endpoint.end_pos)
+        .with(endpoint.responses.iter().map(|v| {
+            NodeTree::new("response", None, &v.start_pos, &v.end_pos).with(mapping_nodes(&v.mappings))
+        }))
+        .with(error_nodes(&endpoint.error_mappings))
+        .with([pipeline_node(&endpoint.pipeline)]);
+    if let Some(cache) = &endpoint.cache {
+        node.children.push(NodeTree::new("cache", None, &cache.start_pos, &cache.start_pos));
+    }
+    node
+}
+
+impl DocumentDef {
+    fn node_tree(&self) -> NodeTree {
+        let mut root = NodeTree::new("document", None, &self.start_pos, &self.end_pos);
+        root.children.push(
+            NodeTree::new("meta", None, &self.meta.start_pos, &self.meta.end_pos)
+                .with(self.meta.pairs.iter().map(|v| NodeTree::new("pair", Some(&v.key), &v.start_pos, &v.end_pos))),
+        );
+        for db in &self.databases {
+            let tables = db.schemas.iter().flat_map(|v| &v.tables).map(|table| {
+                NodeTree::new("table", Some(&table.name), &table.start_pos, &table.end_pos)
+                    .with(
+                        table.columns.iter().map(|v| NodeTree::new("column", Some(&v.name), &v.start_pos, &v.end_pos)),
+                    )
+                    .with(table.constraints.iter().map(|v| {
+                        NodeTree::new("constraint", Some(&v.name), &v.start_pos, &v.end_pos)
+                            .with(mapping_nodes(&v.mappings))
+                    }))
+                    .with(
+                        table
+                            .search_indexes
+                            .iter()
+                            .map(|v| NodeTree::new("search", Some(&v.name), &v.start_pos, &v.end_pos)),
+                    )
+                    .with(table.hypi.iter().map(|v| {
+                        NodeTree::new("hypi", None, &v.start_pos, &v.end_pos).with(mapping_nodes(&v.mappings))
+                    }))
+            });
+            root.children.push(NodeTree::new("db", Some(&db.name), &db.start_pos, &db.end_pos).with(tables));
+        }
+        root.children
+            .extend(self.env.iter().map(|v| NodeTree::new("env", Some(&v.name), &v.start_pos, &v.end_pos)));
+        if let Some(observability) = &self.observability {
+            root.children.push(NodeTree::new("observability", None, &observability.start_pos, &observability.end_pos));
+        }
+        root.children.extend(self.pipelines.iter().map(pipeline_node));
+        root.children.extend(self.jobs.iter().map(|v| NodeTree::new("job", Some(&v.name), &v.start_pos, &v.end_pos)));
+        root.children.extend(
+            self.features.iter().map(|v| NodeTree::new("feature", Some(&v.name), &v.start_pos, &v.end_pos)),
+        );
+        root.children.extend(self.middleware.iter().map(|v| {
+            let node = NodeTree::new("middleware", Some(&v.name), &v.start_pos, &v.end_pos);
+            match &v.runs {
+                Middleware::Step(step) => node.with([step_node(&Step::Docker(step.clone()))]),
+                Middleware::Pipeline(_) => node,
+            }
+        }));
+        root.children.extend(error_nodes(&self.error_mappings));
+        if let Some(rest) = &self.rest {
+            let unversioned = rest.endpoints.iter().filter(|v| v.version.is_none()).map(endpoint_node);
+            let versions = rest.versions.iter().map(|version| {
+                NodeTree::new("api-version", Some(&version.name), &version.start_pos, &version.end_pos).with(
+                    rest.endpoints
+                        .iter()
+                        .filter(|v| v.version.as_deref() == Some(version.name.as_str()))
+                        .map(endpoint_node),
+                )
+            });
+            let mut children: Vec<NodeTree> = unversioned.chain(versions).collect();
+            children.sort_by_key(|v| (v.node.range.start.line, v.node.range.start.column));
+            root.children.push(NodeTree::new("rest", None, &rest.start_pos, &rest.end_pos).with(children));
+        }
+        if let Some(graphql) = &self.graphql {
+            root.children.push(
+                NodeTree::new("graphql", None, &graphql.start_pos, &graphql.end_pos)
+                    .with(graphql.resolvers.iter().map(|v| {
+                        NodeTree::new("resolver", Some(&v.field), &v.start_pos, &v.end_pos)
+                    }))
+                    .with(graphql.federation_keys.iter().map(|v| {
+                        NodeTree::new("federation", Some(&v.table), &v.start_pos, &v.end_pos)
+                    })),
+            );
+        }
+        root.children.sort_by_key(|v| (v.node.range.start.line, v.node.range.start.column));
+        root
+    }
+
+    ///The elements at the position from the outermost, the document, to the innermost e.g. a column's document, db,
+    ///table and column. Empty if the position isn't in the document. Only elements the document keeps the location
+    ///of are included so e.g. `schema` and `apis` are skipped over.
+    pub fn node_at(&self, file: &str, line: u64, column: u64) -> Vec<Node> {
+        let root = self.node_tree();
+        let mut path = vec![];
+        if root.contains(file, line, column) {
+            path.push(root.node.clone());
+        }
+        root.find(file, line, column, &mut path);
+        path
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+///The engine used to render a response body, `<response template="handlebars">`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateEngine {
+    Handlebars,
+    JsonPath,
+    Liquid,
+}
+
+impl FromStr for TemplateEngine {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "handlebars" => Ok(TemplateEngine::Handlebars),
+            "jsonpath" => Ok(TemplateEngine::JsonPath),
+            "liquid" => Ok(TemplateEngine::Liquid),
+            _ => Err(format!(
+                "Invalid template engine '{}'. Supported engines are handlebars, jsonpath OR liquid",
+                input
+            )),
+        }
+    }
+}
+
+impl Display for TemplateEngine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateEngine::Handlebars => f.write_str("handlebars"),
+            TemplateEngine::JsonPath => f.write_str("jsonpath"),
+            TemplateEngine::Liquid => f.write_str("liquid"),
+        }
+    }
+}
+
+const LIQUID_BLOCK_TAGS: &[&str] = &["if", "unless", "for", "case", "capture", "comment", "raw", "tablerow"];
+
+///Checks the syntax of a body template so mistakes are reported when the document is parsed
+///rather than when the response is rendered. The error describes the first problem found.
+pub fn validate_template(engine: &TemplateEngine, body: &str) -> Result<(), String> {
+    match engine {
+        TemplateEngine::Handlebars => validate_handlebars(body),
+        TemplateEngine::JsonPath => validate_json_path(body),
+        TemplateEngine::Liquid => validate_liquid(body),
+    }
+}
+
+///Returns the contents of every `open ... close` tag in `body`
+fn tags<'a>(body: &'a str, open: &str, close: &str) -> Result<Vec<&'a str>, String> {
+    let mut tags = vec![];
+    let mut rest = body;
+    while let Some(start) = rest.find(open) {
+        let after = &rest[start + open.len()..];
+        let end = after
+            .find(close)
+            .ok_or_else(|| format!("'{}' at offset {} is never closed with '{}'", open, body.len() - rest.len() + start, close))?;
+        let tag = &after[..end];
+        if tag.contains(open) {
+            return Err(format!("'{}' is opened again before '{}' closes it", open, close));
+        }
+        tags.push(tag.trim());
+        rest = &after[end + close.len()..];
+    }
+    if let Some(stray) = rest.find(close) {
+        return Err(format!("'{}' at offset {} has no matching '{}'", close, body.len() - rest.len() + stray, open));
+    }
+    Ok(tags)
+}
+
+///Pushes block openings and pops matching closes, returning an error for unbalanced blocks
+fn check_blocks<'a>(stack: &mut Vec<&'a str>, open: Option<&'a str>, close: Option<&'a str>) -> Result<(), String> {
+    if let Some(open) = open {
+        stack.push(open);
+    }
+    if let Some(close) = close {
+        match stack.pop() {
+            Some(open) if open == close => {}
+            Some(open) => return Err(format!("'{}' is closed by '{}'", open, close)),
+            None => return Err(format!("'{}' is closed but was never opened", close)),
+        }
+    }
+    Ok(())
+}
+
+fn validate_handlebars(body: &str) -> Result<(), String> {
+    let mut stack = vec![];
+    for tag in tags(body, "{{", "}}")? {
+        //triple stash {{{raw}}} leaves the third brace in the tag
+        let tag = tag.trim_start_matches('{').trim_end_matches('}').trim();
+        if let Some(block) = tag.strip_prefix('#') {
+            //hosts register their own helpers so any name is accepted, only the blocks have to balance
+            let helper = block.split_whitespace().next().ok_or_else(|| "'{{#}}' has no block helper".to_owned())?;
+            check_blocks(&mut stack, Some(helper), None)?;
+        } else if let Some(block) = tag.strip_prefix('/') {
+            check_blocks(&mut stack, None, Some(block.trim()))?;
+        } else if tag.is_empty() {
+            return Err("'{{}}' is empty".to_owned());
+        }
+    }
+    match stack.pop() {
+        Some(open) => Err(format!("'{{{{#{}}}}}' is never closed", open)),
+        None => Ok(()),
+    }
+}
+
+fn validate_liquid(body: &str) -> Result<(), String> {
+    tags(body, "{{", "}}")?;
+    let mut stack = vec![];
+    for tag in tags(body, "{%", "%}")? {
+        let tag = tag.trim_start_matches('-').trim_end_matches('-').trim();
+        let name = tag.split_whitespace().next().unwrap_or("");
+        //hosts can register their own tags so any other tag is accepted, only the blocks have to balance
+        if name.is_empty() {
+            return Err("'{% %}' has no tag".to_owned());
+        } else if let Some(block) = name.strip_prefix("end") {
+            check_blocks(&mut stack, None, Some(block))?;
+        } else if LIQUID_BLOCK_TAGS.contains(&name) {
+            check_blocks(&mut stack, Some(name), None)?;
+        }
+    }
+    match stack.pop() {
+        Some(open) => Err(format!("'{{% {} %}}' is never closed with '{{% end{} %}}'", open, open)),
+        None => Ok(()),
+    }
+}
+
+fn validate_json_path(body: &str) -> Result<(), String> {
+    let path = body.trim();
+    if !path.starts_with('$') {
+        return Err(format!("'{}' is not a JSON path, it must start with $", path));
+    }
+    let mut stack = vec![];
+    let mut quote = None;
+    for c in path.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[' | '(') => stack.push(c),
+            (None, ']') if stack.pop() != Some('[') => return Err(format!("']' in '{}' has no matching '['", path)),
+            (None, ')') if stack.pop() != Some('(') => return Err(format!("')' in '{}' has no matching '('", path)),
+            _ => {}
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("'{}' has an unclosed quote", path));
+    }
+    match stack.pop() {
+        Some(open) => Err(format!("'{}' in '{}' is never closed", open, path)),
+        None => Ok(()),
+    }
+}
@@ -1,4 +1,10 @@
+use std::collections::HashMap;
 use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use hamlx::haml_parser::{ParseOutput, ParsedDocument};
+use rapid_fs::vfs::{BoundVfs, DomainOptions, MemoryVfs};
 
 pub fn resource_path(path: &str) -> String {
     format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), path)
@@ -7,3 +13,34 @@ pub fn resource_path(path: &str) -> String {
 pub fn read_str_resource(path: &str) -> String {
     fs::read_to_string(resource_path(path)).expect(format!("Error reading test resource {}", path).as_str())
 }
+
+///An in memory file system containing only the given (file name, contents) pairs
+#[allow(dead_code)]
+pub fn memory_fs(files: &[(&str, &str)]) -> Arc<BoundVfs<MemoryVfs>> {
+    Arc::new(BoundVfs::new(
+        DomainOptions {
+            service_id: 123,
+            version: "v1".to_string(),
+        },
+        Arc::new(MemoryVfs {
+            root: PathBuf::from("/private/path/to/services"),
+            data: files
+                .iter()
+                .map(|(name, contents)| {
+                    (
+                        format!("/private/path/to/services/123/versions/v1/{}", name),
+                        contents.to_string(),
+                    )
+                })
+                .collect::<HashMap<_, _>>(),
+        }),
+    ))
+}
+
+///Parses `schema` as schema.xml next to the given (file name, contents) pairs
+#[allow(dead_code)]
+pub fn parse_doc(schema: &str, files: &[(&str, &str)]) -> hamlx::haml_parser::Result<ParseOutput> {
+    let mut files = files.to_vec();
+    files.insert(0, ("schema.xml", schema));
+    ParsedDocument::parse("schema.xml".to_owned(), memory_fs(&files))
+}